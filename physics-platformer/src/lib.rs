@@ -2,6 +2,12 @@ use macroquad::math::{vec2, Rect, Vec2};
 
 use std::collections::HashSet;
 
+#[cfg(feature = "tiled")]
+mod tiled;
+
+#[cfg(feature = "tiled")]
+pub use crate::tiled::{ObjectCollider, TileCollision, TiledObjects};
+
 #[derive(Debug, PartialEq, Clone, Copy)]
 pub enum Tile {
     Empty,
@@ -33,6 +39,7 @@ pub struct World {
     static_tiled_layers: Vec<StaticTiledLayer>,
     solids: Vec<(Solid, Collider)>,
     actors: Vec<(Actor, Collider)>,
+    triggers: Vec<(Trigger, Rect, u8)>,
 }
 
 #[derive(Clone, Debug)]
@@ -66,12 +73,21 @@ pub struct Actor(usize);
 #[derive(Clone, Copy, PartialEq, Eq, Debug, Hash)]
 pub struct Solid(usize);
 
+/// Static tiled layer added to a World
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Hash)]
+pub struct StaticLayer(usize);
+
+/// Tagged area that does not block movement, only reports overlapping actors
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Hash)]
+pub struct Trigger(usize);
+
 impl World {
     pub fn new() -> World {
         World {
             static_tiled_layers: vec![],
             actors: vec![],
             solids: vec![],
+            triggers: vec![],
         }
     }
 
@@ -82,7 +98,7 @@ impl World {
         tile_height: f32,
         width: usize,
         tag: u8,
-    ) {
+    ) -> StaticLayer {
         self.static_tiled_layers.push(StaticTiledLayer {
             static_colliders,
             tile_width,
@@ -90,6 +106,21 @@ impl World {
             width,
             tag,
         });
+        StaticLayer(self.static_tiled_layers.len() - 1)
+    }
+
+    /// Replace the colliders of a static tiled layer, for maps edited or reloaded at runtime.
+    /// "width" is the new width of the layer in tiles, it keeps its tile size and tag.
+    /// Actors already overlapping new solid tiles are not pushed out.
+    pub fn set_static_colliders(
+        &mut self,
+        layer: StaticLayer,
+        static_colliders: Vec<Tile>,
+        width: usize,
+    ) {
+        let layer = &mut self.static_tiled_layers[layer.0];
+        layer.static_colliders = static_colliders;
+        layer.width = width;
     }
     pub fn add_actor(&mut self, pos: Vec2, width: i32, height: i32) -> Actor {
         let actor = Actor(self.actors.len());
@@ -142,6 +173,43 @@ impl World {
         solid
     }
 
+    pub fn add_trigger(&mut self, pos: Vec2, width: i32, height: i32, tag: u8) -> Trigger {
+        let trigger = Trigger(self.triggers.len());
+
+        self.triggers.push((
+            trigger,
+            Rect::new(pos.x, pos.y, width as f32, height as f32),
+            tag,
+        ));
+
+        trigger
+    }
+
+    pub fn trigger_rect(&self, trigger: Trigger) -> Rect {
+        self.triggers[trigger.0].1
+    }
+
+    pub fn trigger_tag(&self, trigger: Trigger) -> u8 {
+        self.triggers[trigger.0].2
+    }
+
+    /// Triggers overlapping the actor collider
+    pub fn actor_triggers(&self, actor: Actor) -> Vec<Trigger> {
+        let rect = self.actors[actor.0].1.rect();
+
+        self.triggers
+            .iter()
+            .filter(|(_, trigger_rect, _)| trigger_rect.overlaps(&rect))
+            .map(|(trigger, _, _)| *trigger)
+            .collect()
+    }
+
+    pub fn trigger_at(&self, pos: Vec2, tag: u8) -> bool {
+        self.triggers
+            .iter()
+            .any(|(_, rect, trigger_tag)| *trigger_tag == tag && rect.contains(pos))
+    }
+
     pub fn set_actor_position(&mut self, actor: Actor, pos: Vec2) {
         let collider = &mut self.actors[actor.0].1;
 
@@ -431,6 +499,10 @@ impl World {
         self.actors[actor.0].1.pos
     }
 
+    pub fn actor_rect(&self, actor: Actor) -> Rect {
+        self.actors[actor.0].1.rect()
+    }
+
     pub fn solid_pos(&self, solid: Solid) -> Vec2 {
         self.solids[solid.0].1.pos
     }
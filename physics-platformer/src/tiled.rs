@@ -0,0 +1,336 @@
+use macroquad::math::{vec2, Rect};
+use macroquad_tiled as tiled;
+
+use std::collections::BTreeMap;
+
+use crate::{Solid, StaticLayer, Tile, Trigger, World};
+
+/// How tiles of a tiled layer are turned into static colliders
+pub enum TileCollision<'a> {
+    /// Look up the tile "type" from tiled (tiled::Tile::attrs)
+    Type(&'a [(&'a str, Tile)]),
+    /// Look up the value of a string property of the tile
+    Property(&'a str, &'a [(&'a str, Tile)]),
+    /// Tiles with the given bool property set are Solid
+    Flag(&'a str),
+}
+
+impl<'a> TileCollision<'a> {
+    pub fn collider(&self, tile: &tiled::Tile) -> Tile {
+        let lookup = |value: &str, table: &[(&str, Tile)]| {
+            table
+                .iter()
+                .find(|(name, _)| *name == value)
+                .map_or(Tile::Empty, |(_, tile)| *tile)
+        };
+
+        match self {
+            TileCollision::Type(table) => lookup(&tile.attrs, table),
+            TileCollision::Property(property, table) => tile
+                .properties
+                .get_string(property)
+                .map_or(Tile::Empty, |value| lookup(value, table)),
+            TileCollision::Flag(property) => {
+                if tile.properties.get_bool(property) == Some(true) {
+                    Tile::Solid
+                } else {
+                    Tile::Empty
+                }
+            }
+        }
+    }
+}
+
+/// What an object of a tiled object layer becomes in the world
+pub enum ObjectCollider {
+    Solid,
+    Trigger(u8),
+    Ignore,
+}
+
+/// Colliders created by World::add_tiled_objects
+#[derive(Debug, Default)]
+pub struct TiledObjects {
+    pub solids: Vec<(u32, Solid)>,
+    pub triggers: Vec<(u32, Trigger)>,
+}
+
+impl World {
+    /// Add a static tiled layer with colliders of the tiled map layer "layer",
+    /// tagged with "tag".
+    /// Colliders follow the orthogonal grid, whatever the map orientation.
+    /// The returned layer can be updated with World::set_static_colliders.
+    pub fn add_tiled_layer(
+        &mut self,
+        map: &tiled::Map,
+        layer: &str,
+        tag: u8,
+        collision: TileCollision,
+    ) -> Result<StaticLayer, tiled::Error> {
+        let tiled_layer = tiled_layer(map, layer)?;
+        let colliders = tiled_layer
+            .data
+            .iter()
+            .map(|tile| {
+                tile.as_ref()
+                    .map_or(Tile::Empty, |tile| collision.collider(tile))
+            })
+            .collect();
+
+        Ok(self.add_static_tiled_layer(
+            colliders,
+            map.raw_tiled_map.tilewidth as f32,
+            map.raw_tiled_map.tileheight as f32,
+            tiled_layer.width as usize,
+            tag,
+        ))
+    }
+
+    /// Add static tiled layers with colliders of the tiled map layer "layer".
+    /// "collider" gives collision type and tag of each tile, one static layer
+    /// is added for each tag in use.
+    /// Returns the added layers with their tags, in increasing tag order.
+    pub fn add_tiled_layer_with(
+        &mut self,
+        map: &tiled::Map,
+        layer: &str,
+        collider: impl Fn(&tiled::Tile) -> (Tile, u8),
+    ) -> Result<Vec<(u8, StaticLayer)>, tiled::Error> {
+        let tiled_layer = tiled_layer(map, layer)?;
+
+        let colliders = tiled_layer
+            .data
+            .iter()
+            .map(|tile| tile.as_ref().map_or((Tile::Empty, 0), &collider))
+            .collect::<Vec<_>>();
+
+        let mut tags = BTreeMap::new();
+        for (ix, (tile, tag)) in colliders.iter().enumerate() {
+            if *tile != Tile::Empty {
+                tags.entry(*tag)
+                    .or_insert_with(|| vec![Tile::Empty; colliders.len()])[ix] = *tile;
+            }
+        }
+
+        Ok(tags
+            .into_iter()
+            .map(|(tag, static_colliders)| {
+                let static_layer = self.add_static_tiled_layer(
+                    static_colliders,
+                    map.raw_tiled_map.tilewidth as f32,
+                    map.raw_tiled_map.tileheight as f32,
+                    tiled_layer.width as usize,
+                    tag,
+                );
+                (tag, static_layer)
+            })
+            .collect())
+    }
+
+    /// Add solids and triggers for the objects of the tiled object layer "layer".
+    /// Objects which are not axis aligned rectangles use their bounding box.
+    pub fn add_tiled_objects(
+        &mut self,
+        map: &tiled::Map,
+        layer: &str,
+        collider: impl Fn(&tiled::Object) -> ObjectCollider,
+    ) -> Result<TiledObjects, tiled::Error> {
+        let tiled_layer = tiled_layer(map, layer)?;
+
+        let mut objects = TiledObjects::default();
+        for object in &tiled_layer.objects {
+            let rect = bounding_box(object);
+            let pos = vec2(rect.x.round(), rect.y.round());
+            let (width, height) = (rect.w.round() as i32, rect.h.round() as i32);
+
+            match collider(object) {
+                ObjectCollider::Solid => objects
+                    .solids
+                    .push((object.id, self.add_solid(pos, width, height))),
+                ObjectCollider::Trigger(tag) => objects
+                    .triggers
+                    .push((object.id, self.add_trigger(pos, width, height, tag))),
+                ObjectCollider::Ignore => {}
+            }
+        }
+        Ok(objects)
+    }
+}
+
+fn tiled_layer<'a>(map: &'a tiled::Map, layer: &str) -> Result<&'a tiled::Layer, tiled::Error> {
+    map.layers()
+        .get(layer)
+        .ok_or_else(|| tiled::Error::LayerNotFound {
+            layer: layer.to_owned(),
+        })
+}
+
+fn bounding_box(object: &tiled::Object) -> Rect {
+    // tile objects are positioned by their bottom left corner
+    if object.gid.is_some() && object.rotation == 0. {
+        return Rect::new(
+            object.world_x,
+            object.world_y - object.world_h,
+            object.world_w,
+            object.world_h,
+        );
+    }
+
+    let points = object.world_points();
+    if points.is_empty() {
+        return Rect::new(object.world_x, object.world_y, 0., 0.);
+    }
+
+    let min = points.iter().fold(points[0], |min, point| min.min(*point));
+    let max = points.iter().fold(points[0], |max, point| max.max(*point));
+    Rect::new(min.x, min.y, max.x - min.x, max.y - min.y)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use macroquad::texture::Texture2D;
+
+    /// A 4x1 map of 8x8 tiles: a wall, a ledge, a lava tile and an empty cell,
+    /// and an object layer "objects" with the given objects
+    fn load(objects: &str) -> tiled::Map {
+        let data = format!(
+            r#"{{"width":4,"height":1,"tilewidth":8,"tileheight":8,
+            "layers":[{{"name":"ground","type":"tilelayer","width":4,"height":1,"data":[1,2,3,0]}},
+                      {{"name":"objects","type":"objectgroup","objects":[{}]}}],
+            "tilesets":[{{"firstgid":1,"name":"ts","image":"ts.png","columns":4,"tilecount":4,"tilewidth":8,"tileheight":8,
+              "tiles":[{{"id":0,"type":"wall","properties":[{{"name":"solid","type":"bool","value":true}}]}},
+                       {{"id":1,"type":"ledge","properties":[{{"name":"kind","type":"string","value":"platform"}}]}},
+                       {{"id":2,"properties":[{{"name":"solid","type":"bool","value":false}},
+                                             {{"name":"kind","type":"string","value":"lava"}}]}}]}}]}}"#,
+            objects
+        );
+        tiled::load_map(&data, &[("ts.png", Texture2D::empty())], &[]).unwrap()
+    }
+
+    fn colliders(world: &World, layer: StaticLayer) -> &[Tile] {
+        &world.static_tiled_layers[layer.0].static_colliders
+    }
+
+    #[test]
+    fn tile_collision() {
+        let map = load("");
+        let table = [("wall", Tile::Solid), ("ledge", Tile::JumpThrough)];
+        let mut world = World::new();
+
+        let by_type = world
+            .add_tiled_layer(&map, "ground", 3, TileCollision::Type(&table))
+            .unwrap();
+        let platforms = [("platform", Tile::JumpThrough), ("wall", Tile::Solid)];
+        let by_property = world
+            .add_tiled_layer(
+                &map,
+                "ground",
+                1,
+                TileCollision::Property("kind", &platforms),
+            )
+            .unwrap();
+        let by_flag = world
+            .add_tiled_layer(&map, "ground", 1, TileCollision::Flag("solid"))
+            .unwrap();
+
+        use Tile::*;
+        assert_eq!(
+            colliders(&world, by_type),
+            [Solid, JumpThrough, Empty, Empty]
+        );
+        assert_eq!(world.static_tiled_layers[by_type.0].tag, 3);
+        assert_eq!(
+            colliders(&world, by_property),
+            [Empty, JumpThrough, Empty, Empty]
+        );
+        // "solid" set to false is not solid
+        assert_eq!(colliders(&world, by_flag), [Solid, Empty, Empty, Empty]);
+
+        assert!(matches!(
+            world.add_tiled_layer(&map, "sky", 1, TileCollision::Flag("solid")),
+            Err(tiled::Error::LayerNotFound { .. })
+        ));
+    }
+
+    #[test]
+    fn tile_collision_by_tag() {
+        let map = load("");
+        let mut world = World::new();
+
+        let layers = world
+            .add_tiled_layer_with(&map, "ground", |tile| match tile.attrs.as_str() {
+                "wall" => (Tile::Solid, 1),
+                "ledge" => (Tile::JumpThrough, 1),
+                _ => (Tile::Collider, 4),
+            })
+            .unwrap();
+
+        use Tile::*;
+        assert_eq!(
+            layers.iter().map(|(tag, _)| *tag).collect::<Vec<_>>(),
+            [1, 4]
+        );
+        assert_eq!(
+            colliders(&world, layers[0].1),
+            [Solid, JumpThrough, Empty, Empty]
+        );
+        assert_eq!(
+            colliders(&world, layers[1].1),
+            [Empty, Empty, Collider, Empty]
+        );
+
+        // the returned layers can be updated later
+        assert_eq!(world.collide_tag(4, vec2(0., 0.), 8, 8), Empty);
+        world.set_static_colliders(layers[1].1, vec![Collider; 4], 4);
+        assert_ne!(world.collide_tag(4, vec2(0., 0.), 8, 8), Empty);
+    }
+
+    #[test]
+    fn object_bounding_boxes() {
+        let map = load(
+            r#"{"id":1,"type":"wall","x":10,"y":20,"width":30,"height":40},
+               {"id":2,"type":"zone","gid":1,"x":8,"y":16,"width":8,"height":8},
+               {"id":3,"type":"zone","x":40,"y":0,"width":20,"height":10,"rotation":90},
+               {"id":4,"type":"zone","x":0,"y":50,"polygon":[{"x":0,"y":0},{"x":16,"y":-8},{"x":8,"y":8}]},
+               {"id":5,"type":"decor","x":0,"y":0,"width":8,"height":8}"#,
+        );
+        let mut world = World::new();
+
+        let objects = world
+            .add_tiled_objects(&map, "objects", |object| match object.ty.as_str() {
+                "wall" => ObjectCollider::Solid,
+                "zone" => ObjectCollider::Trigger(5),
+                _ => ObjectCollider::Ignore,
+            })
+            .unwrap();
+
+        assert_eq!(objects.solids.len(), 1);
+        let (id, solid) = objects.solids[0];
+        assert_eq!(id, 1);
+        assert_eq!(
+            world.solids[solid.0].1.rect(),
+            Rect::new(10., 20., 30., 40.)
+        );
+
+        let triggers = objects
+            .triggers
+            .iter()
+            .map(|(id, trigger)| (*id, world.trigger_rect(*trigger)))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            triggers,
+            [
+                // tile objects are positioned by their bottom left corner
+                (2, Rect::new(8., 8., 8., 8.)),
+                // rotated clockwise around their top left corner
+                (3, Rect::new(30., 0., 10., 20.)),
+                (4, Rect::new(0., 42., 16., 16.)),
+            ]
+        );
+        assert!(objects
+            .triggers
+            .iter()
+            .all(|(_, trigger)| world.trigger_tag(*trigger) == 5));
+    }
+}
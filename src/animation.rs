@@ -0,0 +1,196 @@
+use macroquad::prelude::*;
+
+use crate::assets::TextureAsset;
+use crate::render_queue::Sprite;
+
+// frames of the same size laid out in a row of a texture
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct SpriteSheet {
+   pub texture: Texture2D,
+   pub frame_size: Vec2,
+   // point of the frames put on the position they are drawn at, and rotated around
+   pub pivot: Vec2,
+   // area of the frames matching the collider of the entity
+   pub hitbox: Rect
+}
+
+// where the sprites of an actor are drawn: the middle of the bottom of its collider
+pub fn actor_anchor(collider: Rect) -> Vec2 {
+   vec2(collider.x + collider.w * 0.5, collider.y + collider.h)
+}
+
+impl SpriteSheet {
+   pub fn new(asset: &TextureAsset) -> SpriteSheet {
+      SpriteSheet {
+         texture: asset.texture,
+         frame_size: asset.frame_size(),
+         pivot: asset.pivot(),
+         hitbox: asset.hitbox()
+      }
+   }
+
+   pub fn frame_count(&self) -> usize {
+      (self.texture.width() / self.frame_size.x).max(1.0) as usize
+   }
+
+   // area of the texture showing frame "index"
+   pub fn frame(&self, index: usize) -> Rect {
+      Rect::new(self.frame_size.x * index as f32, 0.0, self.frame_size.x, self.frame_size.y)
+   }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Playback {
+   Loop,
+   // stays on the last frame
+   Once
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct Animation {
+   pub sheet: SpriteSheet,
+   // seconds each frame is shown, one per frame
+   pub frame_durations: Vec<f32>,
+   pub playback: Playback
+}
+
+impl Animation {
+   pub fn frame_count(&self) -> usize {
+      self.frame_durations.len()
+   }
+}
+
+// plays the animation of an entity, each entity owns its own
+pub struct Animator {
+   animation: Animation,
+   frame: usize,
+   // seconds the current frame has been shown
+   elapsed: f32
+}
+
+impl Animator {
+   pub fn new(animation: &Animation) -> Animator {
+      Animator {
+         animation: animation.clone(),
+         frame: 0,
+         elapsed: 0.0
+      }
+   }
+
+   // switch to "animation", from its first frame unless it is already playing
+   pub fn play(&mut self, animation: &Animation) {
+      if self.animation != *animation {
+         *self = Animator::new(animation);
+      }
+   }
+
+   pub fn update(&mut self, dt: f32) {
+      self.elapsed += dt;
+
+      loop {
+         let duration = self.animation.frame_durations[self.frame];
+         if self.elapsed < duration {
+            return;
+         }
+
+         if self.frame + 1 < self.animation.frame_count() {
+            self.frame += 1;
+         } else if self.animation.playback == Playback::Loop {
+            self.frame = 0;
+         } else {
+            self.elapsed = duration;
+            return;
+         }
+         self.elapsed -= duration;
+      }
+   }
+
+   pub fn sheet(&self) -> &SpriteSheet {
+      &self.animation.sheet
+   }
+
+   // the current frame with its pivot at "anchor", rotated around it.
+   // The frame is drawn at its own size unless "params" has a dest_size.
+   pub fn sprite(&self, anchor: Vec2, color: Color, params: DrawTextureParams) -> Sprite {
+      let sheet = &self.animation.sheet;
+      let size = params.dest_size.unwrap_or(sheet.frame_size);
+
+      let mut pivot = sheet.pivot;
+      if params.flip_x {
+         pivot.x = sheet.frame_size.x - pivot.x;
+      }
+      if params.flip_y {
+         pivot.y = sheet.frame_size.y - pivot.y;
+      }
+      let corner = anchor - pivot * size / sheet.frame_size;
+
+      Sprite {
+         texture: sheet.texture,
+         position: corner,
+         color,
+         params: DrawTextureParams {
+            dest_size: Some(size),
+            source: Some(sheet.frame(self.frame)),
+            pivot: Some(anchor),
+            ..params
+         }
+      }
+   }
+}
+
+#[cfg(test)]
+mod tests {
+   use super::*;
+
+   fn animation(frame_durations: &[f32], playback: Playback) -> Animation {
+      Animation {
+         sheet: SpriteSheet {
+            texture: Texture2D::empty(),
+            frame_size: vec2(16.0, 16.0),
+            pivot: vec2(8.0, 16.0),
+            hitbox: Rect::new(0.0, 0.0, 16.0, 16.0)
+         },
+         frame_durations: frame_durations.to_vec(),
+         playback
+      }
+   }
+
+   #[test]
+   fn loop_wraps_around() {
+      let mut animator = Animator::new(&animation(&[0.1, 0.2, 0.3], Playback::Loop));
+
+      animator.update(0.05);
+      assert_eq!(animator.frame, 0);
+      animator.update(0.1);
+      assert_eq!(animator.frame, 1);
+      // a long frame skips several frames, back to the first one
+      animator.update(0.5);
+      assert_eq!(animator.frame, 0);
+      assert!((animator.elapsed - 0.05).abs() < 1e-5);
+   }
+
+   #[test]
+   fn once_stays_on_the_last_frame() {
+      let mut animator = Animator::new(&animation(&[0.1, 0.2], Playback::Once));
+
+      animator.update(0.15);
+      assert_eq!(animator.frame, 1);
+      animator.update(10.0);
+      assert_eq!(animator.frame, 1);
+      assert_eq!(animator.elapsed, 0.2);
+      animator.update(0.1);
+      assert_eq!(animator.frame, 1);
+   }
+
+   #[test]
+   fn play_restarts_other_animations_only() {
+      let walk = animation(&[0.1, 0.1], Playback::Loop);
+      let mut animator = Animator::new(&walk);
+
+      animator.update(0.15);
+      animator.play(&walk);
+      assert_eq!(animator.frame, 1);
+      animator.play(&animation(&[0.1, 0.1], Playback::Once));
+      assert_eq!(animator.frame, 0);
+   }
+}
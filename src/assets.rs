@@ -0,0 +1,537 @@
+use macroquad::audio::{self, Sound};
+use macroquad::prelude::*;
+
+use std::collections::HashMap;
+use std::fs;
+
+use crate::animation::{Animation, Playback, SpriteSheet};
+use crate::tilemap::{fields, TileMapError};
+
+// a problem loading the game assets, lines and columns start at 1
+#[derive(Debug)]
+pub enum AssetError {
+   // a manifest line that could not be understood
+   Manifest { path: String, line: usize, column: usize, message: String },
+   // an asset used by the game but not listed in the manifest
+   UnknownId { kind: &'static str, id: String },
+   Load { path: String, error: String },
+   Map { path: String, error: TileMapError },
+}
+
+impl std::fmt::Display for AssetError {
+   fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+      match self {
+         AssetError::Manifest { path, line, column, message } =>
+            write!(f, "{}, line {}, column {}: {}", path, line, column, message),
+         AssetError::UnknownId { kind, id } =>
+            write!(f, "the asset manifest has no {} named \"{}\"", kind, id),
+         AssetError::Load { path, error } =>
+            write!(f, "could not load {}: {}", path, error),
+         AssetError::Map { path, error } =>
+            write!(f, "{}: {}", path, error),
+      }
+   }
+}
+
+impl std::error::Error for AssetError {}
+
+pub struct TextureAsset {
+   pub path: String,
+   pub texture: Texture2D,
+   // size of the frames of a sprite sheet laid out in a row, None for single images
+   pub frame_size: Option<Vec2>,
+   // point of the frames put on the position they are drawn at, the middle of the frame by default
+   pub pivot: Option<Vec2>,
+   // area of the frames matching the collider of the entity, the whole frame by default
+   pub hitbox: Option<Rect>
+}
+
+impl TextureAsset {
+   pub fn frame_size(&self) -> Vec2 {
+      self.frame_size.unwrap_or_else(|| vec2(self.texture.width(), self.texture.height()))
+   }
+
+   pub fn pivot(&self) -> Vec2 {
+      self.pivot.unwrap_or_else(|| self.frame_size() * 0.5)
+   }
+
+   pub fn hitbox(&self) -> Rect {
+      self.hitbox.unwrap_or_else(|| Rect::new(0.0, 0.0, self.frame_size().x, self.frame_size().y))
+   }
+}
+
+#[derive(PartialEq, Clone, Copy)]
+enum AssetKind {
+   Texture,
+   Sound,
+   Font,
+   Map,
+   Sprite,
+   Animation
+}
+
+impl AssetKind {
+   // whether assets of this kind are files to load, others describe loaded textures
+   fn is_file(self) -> bool {
+      !matches!(self, AssetKind::Sprite | AssetKind::Animation)
+   }
+}
+
+// pivot and hitbox of a sprite line of the manifest, its texture being the entry id
+struct SpriteEntry {
+   line: usize,
+   id_column: usize,
+   pivot: Vec2,
+   hitbox: Option<Rect>
+}
+
+// whether "hitbox" lies within frames of "frame_size", touching their edges is fine
+fn hitbox_fits(hitbox: Rect, frame_size: Vec2) -> bool {
+   hitbox.x >= 0.0 && hitbox.y >= 0.0 && hitbox.right() <= frame_size.x && hitbox.bottom() <= frame_size.y
+}
+
+// frames of an animation line of the manifest, its texture being the entry path
+struct AnimationEntry {
+   line: usize,
+   texture_column: usize,
+   frame_count_column: usize,
+   frame_count: usize,
+   playback: Playback,
+   // a single duration applies to every frame
+   frame_durations: Vec<f32>
+}
+
+// a line of the manifest
+struct ManifestEntry {
+   kind: AssetKind,
+   id: String,
+   path: String,
+   frame_size: Option<Vec2>,
+   sprite: Option<SpriteEntry>,
+   animation: Option<AnimationEntry>
+}
+
+// Manifest files list the assets by kind, each with the id the game uses:
+//
+// #Textures#
+// id, path[, frame width, frame height]
+// #Sounds#
+// id, path
+// #Fonts#
+// id, path
+// #Maps#
+// id, path
+// #Sprites#
+// texture id, pivot x, pivot y[, hitbox x, hitbox y, hitbox width, hitbox height]
+// #Animations#
+// id, texture id, frame count, loop|once, frame duration[, frame duration...]
+//
+// Textures with a frame size are sprite sheets, their frames laid out in a row.
+// Sprites place the frames of a texture, in pixels from the top left corner of a frame:
+// the pivot is put on the position the sprite is drawn at and the hitbox covers the collider.
+// Animations give either one duration in seconds for all their frames or one per frame.
+fn parse_manifest(path: &str, file: &str) -> Result<Vec<ManifestEntry>, AssetError> {
+   let mut entries: Vec<ManifestEntry> = Vec::new();
+   let mut kind = None;
+
+   for (line_index, line) in file.lines().enumerate() {
+      let error = |column: usize, message: String| AssetError::Manifest {
+         path: path.to_string(),
+         line: line_index + 1,
+         column,
+         message
+      };
+
+      let section = [
+         ("#Textures#", AssetKind::Texture),
+         ("#Sounds#", AssetKind::Sound),
+         ("#Fonts#", AssetKind::Font),
+         ("#Maps#", AssetKind::Map),
+         ("#Sprites#", AssetKind::Sprite),
+         ("#Animations#", AssetKind::Animation),
+      ];
+      if let Some((_, section_kind)) = section.iter().find(|(header, _)| line.contains(header)) {
+         kind = Some(*section_kind);
+         continue;
+      }
+
+      if line.trim().is_empty() {
+         continue;
+      }
+
+      let kind = kind.ok_or_else(|| error(1,
+         "expected #Textures#, #Sounds#, #Fonts#, #Maps#, #Sprites# or #Animations# before any content".to_string()))?;
+      let line_fields = fields(line);
+      let end_column = line.chars().count() + 1;
+
+      let (id_column, id) = line_fields[0];
+      let path = match line_fields.get(1) {
+         _ if kind == AssetKind::Sprite => String::new(),
+         Some((_, path)) if !id.is_empty() && !path.is_empty() => path.to_string(),
+         _ => return Err(error(end_column, "assets are written as \"id, path\"".to_string())),
+      };
+
+      let max_fields = match kind {
+         AssetKind::Texture => 4,
+         AssetKind::Sprite => 7,
+         // checked against the frame count below
+         AssetKind::Animation => usize::MAX,
+         _ => 2,
+      };
+      if let Some((column, _)) = line_fields.get(max_fields) {
+         return Err(error(*column, "unexpected column".to_string()));
+      }
+
+      let sprite = if kind == AssetKind::Sprite {
+         let mut numbers = Vec::new();
+         for (column, text) in &line_fields[1..] {
+            numbers.push(text.parse::<f32>().map_err(|_| error(*column, format!("\"{}\" is not a number", text)))?);
+         }
+
+         let hitbox = match numbers.len() {
+            2 => None,
+            6 if numbers[4] >= 1.0 && numbers[5] >= 1.0 => Some(Rect::new(numbers[2], numbers[3], numbers[4], numbers[5])),
+            6 => return Err(error(line_fields[5].0,
+               "hitboxes are at least one pixel wide and high".to_string())),
+            _ => return Err(error(end_column,
+               "sprites are written as \"texture id, pivot x, pivot y\", optionally followed by \"hitbox x, y, width, height\"".to_string())),
+         };
+
+         Some(SpriteEntry {
+            line: line_index + 1,
+            id_column,
+            pivot: vec2(numbers[0], numbers[1]),
+            hitbox
+         })
+      } else {
+         None
+      };
+
+      let animation = if kind == AssetKind::Animation {
+         let (frame_count_column, frame_count) = match line_fields.get(2) {
+            Some((column, text)) => (*column, text.parse::<usize>().ok().filter(|count| *count >= 1).ok_or_else(|| {
+               error(*column, format!("\"{}\" is not a frame count", text))
+            })?),
+            None => return Err(error(end_column, "missing frame count column".to_string())),
+         };
+         let playback = match line_fields.get(3) {
+            Some((_, "loop")) => Playback::Loop,
+            Some((_, "once")) => Playback::Once,
+            Some((column, text)) => return Err(error(*column, format!("expected loop or once, found \"{}\"", text))),
+            None => return Err(error(end_column, "missing loop or once column".to_string())),
+         };
+
+         let mut frame_durations = Vec::new();
+         for (column, text) in &line_fields[4.min(line_fields.len())..] {
+            let duration = text.parse::<f32>().ok().filter(|duration| *duration > 0.0).ok_or_else(|| {
+               error(*column, format!("\"{}\" is not a frame duration in seconds", text))
+            })?;
+            frame_durations.push(duration);
+         }
+         match frame_durations.len() {
+            0 => return Err(error(end_column, "missing frame duration column".to_string())),
+            1 => frame_durations = vec![frame_durations[0]; frame_count],
+            count if count != frame_count => return Err(error(end_column,
+               format!("expected 1 or {} frame durations, found {}", frame_count, count))),
+            _ => (),
+         }
+
+         Some(AnimationEntry {
+            line: line_index + 1,
+            texture_column: line_fields[1].0,
+            frame_count_column,
+            frame_count,
+            playback,
+            frame_durations
+         })
+      } else {
+         None
+      };
+
+      let frame_size = match (line_fields.get(2), line_fields.get(3)) {
+         _ if kind != AssetKind::Texture => None,
+         (None, None) => None,
+         (Some(_), None) => return Err(error(end_column, "missing frame height column".to_string())),
+         (Some(width), Some(height)) => {
+            let mut size = [0.0; 2];
+            for (value, (column, text)) in size.iter_mut().zip([width, height].iter()) {
+               *value = text.parse::<f32>().ok().filter(|value| *value >= 1.0).ok_or_else(|| {
+                  error(*column, format!("\"{}\" is not a frame size in pixels", text))
+               })?;
+            }
+            Some(vec2(size[0], size[1]))
+         }
+         (None, Some(_)) => unreachable!(),
+      };
+
+      if entries.iter().any(|entry| entry.kind == kind && entry.id == id) {
+         return Err(error(id_column, format!("\"{}\" is listed twice", id)));
+      }
+
+      entries.push(ManifestEntry {
+         kind,
+         id: id.to_string(),
+         path,
+         frame_size,
+         sprite,
+         animation
+      });
+   }
+
+   Ok(entries)
+}
+
+fn draw_progress(loaded: usize, total: usize, path: &str) {
+   clear_background(BLACK);
+
+   let size = vec2(screen_width() * 0.5, 24.0);
+   let pos = vec2(screen_width(), screen_height()) / 2.0 - size / 2.0;
+   let progress = loaded as f32 / total.max(1) as f32;
+
+   draw_rectangle(pos.x, pos.y, size.x * progress, size.y, WHITE);
+   draw_rectangle_lines(pos.x, pos.y, size.x, size.y, 2.0, WHITE);
+   draw_text(&format!("Loading {}", path), pos.x, pos.y + size.y + 30.0, 24.0, WHITE);
+}
+
+// the textures, sounds, fonts, maps and animations of the game, by manifest id
+pub struct Assets {
+   textures: HashMap<String, TextureAsset>,
+   sounds: HashMap<String, Sound>,
+   fonts: HashMap<String, Font>,
+   // maps need a World, they are only located here
+   maps: HashMap<String, String>,
+   animations: HashMap<String, Animation>
+}
+
+impl Assets {
+   // load every asset of the manifest at "path", showing a progress bar
+   pub async fn load(path: &str) -> Result<Assets, AssetError> {
+      let file = fs::read_to_string(path).map_err(|error| AssetError::Load {
+         path: path.to_string(),
+         error: error.to_string()
+      })?;
+      // sprites and animations have no file of their own, they are read once the textures are loaded
+      let (entries, mut descriptions): (Vec<_>, Vec<_>) = parse_manifest(path, &file)?
+         .into_iter()
+         .partition(|entry| entry.kind.is_file());
+      // animations copy the pivot and hitbox of their sprites
+      descriptions.sort_by_key(|entry| entry.kind == AssetKind::Animation);
+
+      let mut assets = Assets {
+         textures: HashMap::new(),
+         sounds: HashMap::new(),
+         fonts: HashMap::new(),
+         maps: HashMap::new(),
+         animations: HashMap::new()
+      };
+
+      let total = entries.len();
+      for (index, entry) in entries.into_iter().enumerate() {
+         draw_progress(index, total, &entry.path);
+         next_frame().await;
+
+         let load_error = |error: String| AssetError::Load { path: entry.path.clone(), error };
+
+         match entry.kind {
+            AssetKind::Texture => {
+               let texture = load_texture(&entry.path).await
+                  .map_err(|error| load_error(error.to_string()))?;
+               assets.textures.insert(entry.id, TextureAsset {
+                  path: entry.path,
+                  texture,
+                  frame_size: entry.frame_size,
+                  pivot: None,
+                  hitbox: None
+               });
+            }
+            AssetKind::Sound => {
+               let sound = audio::load_sound(&entry.path).await
+                  .map_err(|error| load_error(error.to_string()))?;
+               assets.sounds.insert(entry.id, sound);
+            }
+            AssetKind::Font => {
+               let font = load_ttf_font(&entry.path).await
+                  .map_err(|error| load_error(error.to_string()))?;
+               assets.fonts.insert(entry.id, font);
+            }
+            AssetKind::Map => {
+               if !std::path::Path::new(&entry.path).exists() {
+                  return Err(load_error("file not found".to_string()));
+               }
+               assets.maps.insert(entry.id, entry.path);
+            }
+            AssetKind::Sprite | AssetKind::Animation => unreachable!(),
+         }
+      }
+
+      for entry in descriptions {
+         if let Some(sprite) = &entry.sprite {
+            let error = |message: String| AssetError::Manifest {
+               path: path.to_string(),
+               line: sprite.line,
+               column: sprite.id_column,
+               message
+            };
+
+            let texture = assets.textures.get_mut(&entry.id)
+               .ok_or_else(|| error(format!("no texture named \"{}\"", entry.id)))?;
+            let frame_size = texture.frame_size();
+            if let Some(hitbox) = sprite.hitbox.filter(|hitbox| !hitbox_fits(*hitbox, frame_size)) {
+               return Err(error(format!("hitbox {}x{} at {}, {} is outside the {}x{} frames of \"{}\"",
+                  hitbox.w, hitbox.h, hitbox.x, hitbox.y, frame_size.x, frame_size.y, entry.id)));
+            }
+
+            texture.pivot = Some(sprite.pivot);
+            texture.hitbox = sprite.hitbox;
+            continue;
+         }
+
+         let frames = entry.animation.expect("animation entries have frames");
+         let error = |column: usize, message: String| AssetError::Manifest {
+            path: path.to_string(),
+            line: frames.line,
+            column,
+            message
+         };
+
+         let sheet = match assets.textures.get(&entry.path) {
+            Some(texture) => SpriteSheet::new(texture),
+            None => return Err(error(frames.texture_column, format!("no texture named \"{}\"", entry.path))),
+         };
+         if frames.frame_count > sheet.frame_count() {
+            return Err(error(frames.frame_count_column,
+               format!("{} frames, but \"{}\" only has {}", frames.frame_count, entry.path, sheet.frame_count())));
+         }
+
+         assets.animations.insert(entry.id, Animation {
+            sheet,
+            frame_durations: frames.frame_durations,
+            playback: frames.playback
+         });
+      }
+
+      Ok(assets)
+   }
+
+   pub fn texture_asset(&self, id: &str) -> Result<&TextureAsset, AssetError> {
+      self.textures.get(id).ok_or_else(|| AssetError::UnknownId { kind: "texture", id: id.to_string() })
+   }
+
+   pub fn texture(&self, id: &str) -> Result<Texture2D, AssetError> {
+      self.texture_asset(id).map(|asset| asset.texture)
+   }
+
+   // every texture, for hot reloading
+   pub fn textures(&self) -> impl Iterator<Item = &TextureAsset> {
+      self.textures.values()
+   }
+
+   pub fn sound(&self, id: &str) -> Result<Sound, AssetError> {
+      self.sounds.get(id).copied().ok_or_else(|| AssetError::UnknownId { kind: "sound", id: id.to_string() })
+   }
+
+   pub fn font(&self, id: &str) -> Result<Font, AssetError> {
+      self.fonts.get(id).copied().ok_or_else(|| AssetError::UnknownId { kind: "font", id: id.to_string() })
+   }
+
+   pub fn animation(&self, id: &str) -> Result<Animation, AssetError> {
+      self.animations.get(id).cloned().ok_or_else(|| AssetError::UnknownId { kind: "animation", id: id.to_string() })
+   }
+
+   // path of the map file
+   pub fn map(&self, id: &str) -> Result<&str, AssetError> {
+      self.maps.get(id).map(|path| path.as_str()).ok_or_else(|| AssetError::UnknownId { kind: "map", id: id.to_string() })
+   }
+}
+
+// show "error" until escape is pressed
+pub async fn error_screen(error: &str) {
+   eprintln!("{}", error);
+
+   loop {
+      if is_key_pressed(KeyCode::Escape) {
+         break;
+      }
+
+      clear_background(BLACK);
+      let lines = ["The game could not start:", error, "", "Press Escape to quit"];
+      for (index, line) in lines.iter().enumerate() {
+         draw_text(line, 40.0, 80.0 + index as f32 * 36.0, 32.0, WHITE);
+      }
+
+      next_frame().await;
+   }
+}
+
+#[cfg(test)]
+mod tests {
+   use super::*;
+
+   // line and column of the manifest error for "file"
+   fn error_at(file: &str) -> (usize, usize) {
+      match parse_manifest("manifest.txt", file) {
+         Err(AssetError::Manifest { line, column, .. }) => (line, column),
+         Err(error) => panic!("unexpected error {}", error),
+         Ok(_) => panic!("the manifest was accepted"),
+      }
+   }
+
+   #[test]
+   fn entries() {
+      let entries = parse_manifest("manifest.txt", "#Textures#\n\
+         panda, assets/panda.png, 32, 16\n\
+         #Sounds#\n\
+         music, assets/music.wav\n\
+         #Sprites#\n\
+         panda, 16, 15, 4, 2, 24, 14\n\
+         #Animations#\n\
+         walk, panda, 3, loop, 0.1\n\
+         fall, panda, 2, once, 0.1, 0.5").unwrap();
+
+      assert_eq!(entries.len(), 5);
+      assert!(entries[0].kind == AssetKind::Texture && entries[0].path == "assets/panda.png");
+      assert_eq!(entries[0].frame_size, Some(vec2(32.0, 16.0)));
+      assert!(entries[1].kind == AssetKind::Sound && entries[1].frame_size.is_none());
+
+      let sprite = entries[2].sprite.as_ref().unwrap();
+      assert_eq!((sprite.line, sprite.id_column), (6, 1));
+      assert_eq!(sprite.pivot, vec2(16.0, 15.0));
+      assert_eq!(sprite.hitbox, Some(Rect::new(4.0, 2.0, 24.0, 14.0)));
+
+      let walk = entries[3].animation.as_ref().unwrap();
+      assert_eq!((walk.line, walk.texture_column, walk.frame_count_column), (8, 7, 14));
+      assert_eq!(walk.playback, Playback::Loop);
+      // a single duration is used for every frame
+      assert_eq!(walk.frame_durations, [0.1, 0.1, 0.1]);
+      let fall = entries[4].animation.as_ref().unwrap();
+      assert_eq!(fall.playback, Playback::Once);
+      assert_eq!(fall.frame_durations, [0.1, 0.5]);
+   }
+
+   #[test]
+   fn hitbox_inside_the_frame() {
+      let frame_size = vec2(32.0, 16.0);
+
+      assert!(hitbox_fits(Rect::new(0.0, 0.0, 32.0, 16.0), frame_size));
+      assert!(hitbox_fits(Rect::new(12.0, 6.0, 20.0, 10.0), frame_size));
+      assert!(!hitbox_fits(Rect::new(12.0, 6.0, 21.0, 10.0), frame_size));
+      assert!(!hitbox_fits(Rect::new(-1.0, 0.0, 8.0, 8.0), frame_size));
+   }
+
+   #[test]
+   fn error_positions() {
+      assert_eq!(error_at("panda, assets/panda.png"), (1, 1));
+      // missing columns are reported at the end of the line
+      assert_eq!(error_at("#Sounds#\nmusic"), (2, 6));
+      assert_eq!(error_at("#Textures#\npanda, a.png, 32"), (2, 17));
+      assert_eq!(error_at("#Textures#\npanda, a.png, 32, 0"), (2, 19));
+      assert_eq!(error_at("#Sounds#\nmusic, a.wav, loud"), (2, 15));
+      assert_eq!(error_at("#Maps#\nmap, a.txt\n\nmap, b.txt"), (4, 1));
+      assert_eq!(error_at("#Sprites#\npanda, 16, x"), (2, 12));
+      assert_eq!(error_at("#Sprites#\npanda, 16, 15, 0, 0, 0, 8"), (2, 22));
+      assert_eq!(error_at("#Animations#\nwalk, panda, 0, loop, 0.1"), (2, 14));
+      assert_eq!(error_at("#Animations#\nwalk, panda, 2, twice, 0.1"), (2, 17));
+      assert_eq!(error_at("#Animations#\nwalk, panda, 3, loop, 0.1, 0.2"), (2, 31));
+      assert_eq!(error_at("#Animations#\nwalk, panda, 1, loop, -1"), (2, 23));
+   }
+}
@@ -0,0 +1,257 @@
+use macroquad::prelude::*;
+use macroquad_platformer::*;
+
+use crate::tilemap::TileMap;
+
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum EditorTool {
+   Tiles,
+   Spawns,
+   Bamboo
+}
+
+impl EditorTool {
+   fn next(self) -> EditorTool {
+      match self {
+         EditorTool::Tiles => EditorTool::Spawns,
+         EditorTool::Spawns => EditorTool::Bamboo,
+         EditorTool::Bamboo => EditorTool::Tiles,
+      }
+   }
+}
+
+// palette of tile types drawn at the bottom of the screen, in screen pixels
+const PALETTE_TILE_SIZE: f32 = 48.0;
+const PALETTE_MARGIN: f32 = 8.0;
+
+// spawn points closer than this to the mouse are removed by right clicks, in world pixels
+const SPAWN_PICK_DISTANCE: f32 = 8.0;
+
+// developer mode painting the tile map, its spawn points and bamboo regions
+// while the simulation is paused
+pub struct Editor {
+   pub active: bool,
+   // map file written on save
+   path: String,
+   tool: EditorTool,
+   // index in TileMap::layers
+   layer: usize,
+   // index in TileMap::tile_textures
+   brush: usize,
+   // first corner of the bamboo region being drawn, in world pixels
+   drag_start: Option<Vec2>,
+   status: String,
+   paused_at: f64
+}
+
+impl Editor {
+   pub fn new(path: &str, active: bool) -> Editor {
+      Editor {
+         active,
+         path: path.to_string(),
+         tool: EditorTool::Tiles,
+         layer: 0,
+         brush: 0,
+         drag_start: None,
+         status: String::new(),
+         paused_at: get_time()
+      }
+   }
+
+   // enter or leave the editor, returns how long the game was paused when leaving it
+   pub fn toggle(&mut self) -> Option<f64> {
+      self.active = !self.active;
+      self.drag_start = None;
+
+      if self.active {
+         self.paused_at = get_time();
+         None
+      } else {
+         Some(get_time() - self.paused_at)
+      }
+   }
+
+   // mouse position in world pixels, "view" being the world area shown at "screen" on the screen
+   fn mouse_world_pos(view: Rect, screen: Rect) -> Vec2 {
+      let mouse = Vec2::from(mouse_position());
+      view.point() + (mouse - screen.point()) / screen.size() * view.size()
+   }
+
+   // palette slot under the mouse
+   fn palette_slot(tilemap: &TileMap) -> Option<usize> {
+      let (x, y) = mouse_position();
+      let top = screen_height() - PALETTE_TILE_SIZE - PALETTE_MARGIN;
+      if y < top || x < PALETTE_MARGIN {
+         return None;
+      }
+
+      let slot = ((x - PALETTE_MARGIN) / (PALETTE_TILE_SIZE + PALETTE_MARGIN)) as usize;
+      Some(slot).filter(|slot| *slot < tilemap.tile_textures.len())
+   }
+
+   pub fn update(&mut self, tilemap: &mut TileMap, world: &mut World, view: Rect, screen: Rect) {
+      let ctrl = is_key_down(KeyCode::LeftControl) || is_key_down(KeyCode::RightControl);
+      let tile_count = tilemap.tile_textures.len();
+
+      if is_key_pressed(KeyCode::Tab) {
+         self.tool = self.tool.next();
+         self.drag_start = None;
+      }
+      if is_key_pressed(KeyCode::L) && !tilemap.layers.is_empty() {
+         self.layer = (self.layer + 1) % tilemap.layers.len();
+      }
+      if tile_count > 0 {
+         let (_, wheel) = mouse_wheel();
+         if is_key_pressed(KeyCode::E) || wheel < 0.0 {
+            self.brush = (self.brush + 1) % tile_count;
+         }
+         if is_key_pressed(KeyCode::Q) || wheel > 0.0 {
+            self.brush = (self.brush + tile_count - 1) % tile_count;
+         }
+      }
+      if ctrl && is_key_pressed(KeyCode::S) {
+         self.status = match tilemap.save(&self.path) {
+            Ok(()) => format!("saved {}", self.path),
+            Err(error) => error.to_string(),
+         };
+      }
+
+      if let Some(slot) = Editor::palette_slot(tilemap) {
+         if is_mouse_button_pressed(MouseButton::Left) {
+            self.brush = slot;
+            self.tool = EditorTool::Tiles;
+         }
+         return;
+      }
+
+      let pos = Editor::mouse_world_pos(view, screen);
+
+      match self.tool {
+         EditorTool::Tiles => {
+            let brush = if is_mouse_button_down(MouseButton::Left) && tile_count > 0 {
+               Some(self.brush)
+            } else if is_mouse_button_down(MouseButton::Right) {
+               None
+            } else {
+               return;
+            };
+
+            let layer = match tilemap.layers.get(self.layer) {
+               Some(layer) => layer.name.clone(),
+               None => return
+            };
+            let loc = tilemap.world_to_tile(pos);
+            let current = tilemap.tile_at(&layer, loc).map(|tile| tile.texture_index);
+
+            if current != brush && tilemap.set_tile(&layer, loc, brush) {
+               tilemap.rebuild_colliders(world);
+            }
+         }
+         EditorTool::Spawns => {
+            if is_mouse_button_pressed(MouseButton::Left) && tilemap.bounds().contains(pos) {
+               tilemap.spawn_points.push(pos.round());
+            }
+            if is_mouse_button_pressed(MouseButton::Right) {
+               let nearest = tilemap.spawn_points
+                  .iter()
+                  .position(|point| point.distance(pos) < SPAWN_PICK_DISTANCE);
+               if let Some(index) = nearest {
+                  tilemap.spawn_points.remove(index);
+               }
+            }
+         }
+         EditorTool::Bamboo => {
+            if is_mouse_button_pressed(MouseButton::Left) {
+               self.drag_start = Some(pos.round());
+            }
+            if is_mouse_button_released(MouseButton::Left) {
+               if let Some(start) = self.drag_start.take() {
+                  let region = Editor::region(start, pos.round());
+                  if region.w >= 1.0 && region.h >= 1.0 {
+                     tilemap.bamboo_regions.push(region);
+                  }
+               }
+            }
+            if is_mouse_button_pressed(MouseButton::Right) {
+               let under_mouse = tilemap.bamboo_regions
+                  .iter()
+                  .rposition(|region| region.contains(pos));
+               if let Some(index) = under_mouse {
+                  tilemap.bamboo_regions.remove(index);
+               }
+            }
+         }
+      }
+   }
+
+   // rect with corners "a" and "b"
+   fn region(a: Vec2, b: Vec2) -> Rect {
+      let min = a.min(b);
+      let size = (a - b).abs();
+      Rect::new(min.x, min.y, size.x, size.y)
+   }
+
+   // draw spawn points, bamboo regions and the cursor, with the world camera
+   pub fn draw_world(&self, tilemap: &TileMap, view: Rect, screen: Rect) {
+      let pos = Editor::mouse_world_pos(view, screen);
+
+      for region in &tilemap.bamboo_regions {
+         draw_rectangle(region.x, region.y, region.w, region.h, Color::new(0.0, 1.0, 0.0, 0.2));
+         draw_rectangle_lines(region.x, region.y, region.w, region.h, 1.0, GREEN);
+      }
+      for point in &tilemap.spawn_points {
+         draw_circle_lines(point.x, point.y, 4.0, 1.0, MAGENTA);
+      }
+
+      match self.tool {
+         EditorTool::Tiles => {
+            let loc = tilemap.world_to_tile(pos);
+            if tilemap.bounds().contains(pos) {
+               let corner = tilemap.tile_to_world(loc);
+               let size = tilemap.tile_size();
+               draw_rectangle_lines(corner.x, corner.y, size.x, size.y, 1.0, YELLOW);
+            }
+         }
+         EditorTool::Spawns => draw_circle_lines(pos.x, pos.y, 4.0, 1.0, YELLOW),
+         EditorTool::Bamboo => {
+            if let Some(start) = self.drag_start {
+               let region = Editor::region(start, pos.round());
+               draw_rectangle_lines(region.x, region.y, region.w, region.h, 1.0, YELLOW);
+            }
+         }
+      }
+   }
+
+   // draw the palette and the editor status, in screen pixels
+   pub fn draw_ui(&self, tilemap: &TileMap) {
+      let top = screen_height() - PALETTE_TILE_SIZE - PALETTE_MARGIN;
+      draw_rectangle(0.0, top - PALETTE_MARGIN, screen_width(), screen_height() - top + PALETTE_MARGIN,
+         Color::new(0.0, 0.0, 0.0, 0.6));
+
+      for (index, texture) in tilemap.tile_textures.iter().enumerate() {
+         let x = PALETTE_MARGIN + index as f32 * (PALETTE_TILE_SIZE + PALETTE_MARGIN);
+         draw_texture_ex(*texture,
+            x,
+            top,
+            WHITE,
+            DrawTextureParams {
+               dest_size: Some(vec2(PALETTE_TILE_SIZE, PALETTE_TILE_SIZE)),
+               ..Default::default()
+            });
+
+         if index == self.brush {
+            draw_rectangle_lines(x - 2.0, top - 2.0, PALETTE_TILE_SIZE + 4.0, PALETTE_TILE_SIZE + 4.0, 4.0, YELLOW);
+         }
+      }
+
+      let layer = tilemap.layers.get(self.layer).map_or("-", |layer| layer.name.as_str());
+      let lines = [
+         format!("EDITOR  tool: {:?}  layer: {}  tile: {}", self.tool, layer, self.brush),
+         "[Tab] tool  [L] layer  [Q/E, wheel] tile  [left/right click] paint/erase  [Ctrl+S] save  [F2] play".to_string(),
+         self.status.clone(),
+      ];
+      for (index, line) in lines.iter().enumerate() {
+         draw_text(line, 20.0, 100.0 + index as f32 * 24.0, 24.0, YELLOW);
+      }
+   }
+}
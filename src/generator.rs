@@ -0,0 +1,380 @@
+use macroquad_platformer::*;
+
+use crate::tilemap::{parse_tilemap, TileMap, TileMapError};
+
+// size of generated maps in tiles, picked for each seed. The largest fills the
+// area shown by the game camera, smaller ones are centred in it
+const MIN_WIDTH: usize = 11;
+const MAX_WIDTH: usize = 15;
+const MIN_HEIGHT: usize = 7;
+const MAX_HEIGHT: usize = 9;
+// tile size of the map files, in world pixels
+const TILE_SIZE: usize = 32;
+// panda spawn points, on random grass tiles
+const SPAWN_COUNT: usize = 3;
+
+// tile types of generated maps with whether they collide and are water,
+// same order as assets/map.txt
+const TILES: &[(&str, bool, bool)] = &[
+   ("assets/grass1.png", false, false),
+   ("assets/Tile2.png", true, false),
+   ("assets/grass2.png", false, false),
+   ("assets/grass3.png", false, false),
+   ("assets/water_bottom.png", true, true),
+   ("assets/water_left.png", true, true),
+   ("assets/water_right.png", true, true),
+   ("assets/water_top.png", true, true),
+   ("assets/water_bottom_left.png", true, true),
+   ("assets/water_top_left.png", true, true),
+   ("assets/water_top_right.png", true, true),
+   ("assets/water_bottom_right.png", true, true),
+   ("assets/rock_01.png", true, false),
+   ("assets/rock_02.png", true, false),
+];
+
+const GRASS: usize = 0;
+const GRASS_VARIANTS: [usize; 2] = [2, 3];
+const WATER_BOTTOM: usize = 4;
+const WATER_LEFT: usize = 5;
+const WATER_RIGHT: usize = 6;
+const WATER_TOP: usize = 7;
+const WATER_BOTTOM_LEFT: usize = 8;
+const WATER_TOP_LEFT: usize = 9;
+const WATER_TOP_RIGHT: usize = 10;
+const WATER_BOTTOM_RIGHT: usize = 11;
+const ROCKS: [usize; 2] = [12, 13];
+
+// the water tiles have no inner corners, so ponds are two rows high with their
+// shore tiles facing inwards, and the enclosure itself is always rectangular
+const POND_HEIGHT: usize = 2;
+const MAX_POND_WIDTH: usize = 3;
+
+// tries to place each pond or rock before giving up on it
+const PLACEMENT_ATTEMPTS: usize = 20;
+
+// SplitMix64, so a seed gives the same map whatever else uses macroquad::rand
+struct Rng(u64);
+
+impl Rng {
+   fn next(&mut self) -> u64 {
+      self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+      let mut z = self.0;
+      z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+      z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+      z ^ (z >> 31)
+   }
+
+   // in low..=high
+   fn range(&mut self, low: usize, high: usize) -> usize {
+      low + (self.next() % (high - low + 1) as u64) as usize
+   }
+
+   fn chance(&mut self, percent: u64) -> bool {
+      self.next() % 100 < percent
+   }
+}
+
+// tile grids of a generated map, row by row
+struct Enclosure {
+   width: usize,
+   height: usize,
+   ground: Vec<usize>,
+   decoration: Vec<Option<usize>>,
+   // tiles the pandas spawn on
+   spawns: Vec<(usize, usize)>
+}
+
+impl Enclosure {
+   fn blocked(&self, x: usize, y: usize) -> bool {
+      let index = y * self.width + x;
+      TILES[self.ground[index]].1 || matches!(self.decoration[index], Some(tile) if TILES[tile].1)
+   }
+
+   // whether the cells from x0, y0 to x1, y1 included are all walkable
+   fn is_free(&self, x0: usize, y0: usize, x1: usize, y1: usize) -> bool {
+      (y0..=y1).all(|y| (x0..=x1).all(|x| !self.blocked(x, y)))
+   }
+
+   // whether every walkable cell can be reached from every other one
+   fn is_connected(&self) -> bool {
+      let width = self.width;
+      let walkable = (0..width * self.height).filter(|index| !self.blocked(index % width, index / width));
+      let start = match walkable.clone().next() {
+         Some(start) => start,
+         None => return false
+      };
+
+      let mut reached = vec![false; width * self.height];
+      let mut stack = vec![start];
+      reached[start] = true;
+
+      while let Some(index) = stack.pop() {
+         let (x, y) = (index % width, index / width);
+         let neighbours = [
+            (x > 0).then(|| index - 1),
+            (x + 1 < width).then(|| index + 1),
+            (y > 0).then(|| index - width),
+            (y + 1 < self.height).then(|| index + width),
+         ];
+
+         for neighbour in neighbours.iter().flatten() {
+            if !reached[*neighbour] && !self.blocked(neighbour % width, neighbour / width) {
+               reached[*neighbour] = true;
+               stack.push(*neighbour);
+            }
+         }
+      }
+
+      walkable.into_iter().all(|index| reached[index])
+   }
+
+   fn border(&mut self) {
+      let (width, height) = (self.width, self.height);
+      for x in 0..width {
+         self.ground[x] = WATER_TOP;
+         self.ground[(height - 1) * width + x] = WATER_BOTTOM;
+      }
+      for y in 0..height {
+         self.ground[y * width] = WATER_LEFT;
+         self.ground[y * width + width - 1] = WATER_RIGHT;
+      }
+      self.ground[0] = WATER_TOP_LEFT;
+      self.ground[width - 1] = WATER_TOP_RIGHT;
+      self.ground[(height - 1) * width] = WATER_BOTTOM_LEFT;
+      self.ground[height * width - 1] = WATER_BOTTOM_RIGHT;
+   }
+
+   // a pond of "width" tiles at x, y, kept only if the grass stays connected
+   fn try_pond(&mut self, x: usize, y: usize, width: usize) -> bool {
+      // one tile of grass around the pond
+      if !self.is_free(x - 1, y - 1, x + width, y + POND_HEIGHT) {
+         return false;
+      }
+
+      let previous = self.ground.clone();
+      for dx in 0..width {
+         let (top, bottom) = match dx {
+            0 => (WATER_BOTTOM_RIGHT, WATER_TOP_RIGHT),
+            _ if dx == width - 1 => (WATER_BOTTOM_LEFT, WATER_TOP_LEFT),
+            _ => (WATER_BOTTOM, WATER_TOP),
+         };
+         self.ground[y * self.width + x + dx] = top;
+         self.ground[(y + 1) * self.width + x + dx] = bottom;
+      }
+
+      if !self.is_connected() {
+         self.ground = previous;
+         return false;
+      }
+      true
+   }
+
+   // a rock at x, y, kept only if the grass stays connected
+   fn try_rock(&mut self, x: usize, y: usize, rock: usize) -> bool {
+      if !self.is_free(x - 1, y - 1, x + 1, y + 1) {
+         return false;
+      }
+
+      let index = y * self.width + x;
+      self.decoration[index] = Some(rock);
+      if !self.is_connected() {
+         self.decoration[index] = None;
+         return false;
+      }
+      true
+   }
+
+   // the map in the map file format
+   fn to_text(&self) -> String {
+      let mut text = String::from("#Tiles#\n");
+      for (index, (path, collider, water)) in TILES.iter().enumerate() {
+         text += &format!("{}, {}, {}", path, index, collider);
+         if *water {
+            text += ", true";
+         }
+         text += "\n";
+      }
+
+      text += "#Map#\n";
+      for row in self.ground.chunks(self.width) {
+         let row: Vec<String> = row.iter().map(|tile| tile.to_string()).collect();
+         text += &row.join(",");
+         text += "\n";
+      }
+
+      text += "#Layer# decoration, true, below\n";
+      for row in self.decoration.chunks(self.width) {
+         let row: Vec<String> = row
+            .iter()
+            .map(|tile| tile.map_or("-".to_string(), |tile| tile.to_string()))
+            .collect();
+         text += &row.join(",");
+         text += "\n";
+      }
+
+      if !self.spawns.is_empty() {
+         text += "#Spawns#\n";
+         for (x, y) in &self.spawns {
+            text += &format!("{}, {}\n", x * TILE_SIZE + TILE_SIZE / 2, y * TILE_SIZE + TILE_SIZE / 2);
+         }
+      }
+
+      text
+   }
+}
+
+fn generate(seed: u64) -> Enclosure {
+   let mut rng = Rng(seed);
+   let (width, height) = (rng.range(MIN_WIDTH, MAX_WIDTH), rng.range(MIN_HEIGHT, MAX_HEIGHT));
+   let mut enclosure = Enclosure {
+      width,
+      height,
+      ground: vec![GRASS; width * height],
+      decoration: vec![None; width * height],
+      spawns: Vec::new()
+   };
+
+   enclosure.border();
+
+   for tile in enclosure.ground.iter_mut().filter(|tile| **tile == GRASS) {
+      if rng.chance(20) {
+         *tile = GRASS_VARIANTS[rng.range(0, GRASS_VARIANTS.len() - 1)];
+      }
+   }
+
+   // ponds and rocks keep off the tiles next to the border
+   let ponds = rng.range(0, 2);
+   for _ in 0..ponds {
+      for _ in 0..PLACEMENT_ATTEMPTS {
+         let width = rng.range(2, MAX_POND_WIDTH);
+         let x = rng.range(2, enclosure.width - 2 - width);
+         let y = rng.range(2, enclosure.height - 2 - POND_HEIGHT);
+         if enclosure.try_pond(x, y, width) {
+            break;
+         }
+      }
+   }
+
+   let rocks = rng.range(3, 6);
+   for _ in 0..rocks {
+      for _ in 0..PLACEMENT_ATTEMPTS {
+         let rock = ROCKS[rng.range(0, ROCKS.len() - 1)];
+         let x = rng.range(2, enclosure.width - 3);
+         let y = rng.range(2, enclosure.height - 3);
+         if enclosure.try_rock(x, y, rock) {
+            break;
+         }
+      }
+   }
+
+   let free: Vec<(usize, usize)> = (0..height)
+      .flat_map(|y| (0..width).map(move |x| (x, y)))
+      .filter(|(x, y)| !enclosure.blocked(*x, *y))
+      .collect();
+   for _ in 0..SPAWN_COUNT.min(free.len()) {
+      enclosure.spawns.push(free[rng.range(0, free.len() - 1)]);
+   }
+
+   enclosure
+}
+
+// a grass enclosure bordered by water with ponds and rocks, the same for a given seed.
+// Every walkable tile can be reached from every other one.
+pub async fn generate_tilemap(seed: u64, world: &mut World) -> Result<TileMap, TileMapError> {
+   parse_tilemap(&generate(seed).to_text(), world).await
+}
+
+#[cfg(test)]
+mod tests {
+   use super::*;
+
+   // walkable tiles of a map file and its width, read back from the text
+   // independently of the generator
+   fn walkable_tiles(text: &str) -> (Vec<bool>, usize, Vec<usize>) {
+      let mut colliders = Vec::new();
+      let mut layers: Vec<Vec<bool>> = Vec::new();
+      let mut spawns = Vec::new();
+      let mut width = 0;
+      let mut section = "";
+
+      for line in text.lines() {
+         if line.starts_with('#') {
+            section = line.split('#').nth(1).unwrap();
+            if section == "Map" || section == "Layer" {
+               layers.push(Vec::new());
+            }
+            continue;
+         }
+
+         let fields: Vec<&str> = line.split(',').map(str::trim).collect();
+         match section {
+            "Tiles" => colliders.push(fields[2] == "true"),
+            "Map" | "Layer" => {
+               width = fields.len();
+               let layer = layers.last_mut().unwrap();
+               layer.extend(fields.iter().map(|field| *field != "-" && colliders[field.parse::<usize>().unwrap()]));
+            }
+            "Spawns" => {
+               let (x, y): (f32, f32) = (fields[0].parse().unwrap(), fields[1].parse().unwrap());
+               spawns.push((y as usize / TILE_SIZE) * width + x as usize / TILE_SIZE);
+            }
+            _ => panic!("unexpected line {}", line),
+         }
+      }
+
+      let walkable = (0..layers[0].len()).map(|index| layers.iter().all(|layer| !layer[index])).collect();
+      (walkable, width, spawns)
+   }
+
+   #[test]
+   fn every_grass_tile_is_reachable() {
+      for seed in 0..1000 {
+         let (walkable, width, spawns) = walkable_tiles(&generate(seed).to_text());
+         assert!(!spawns.is_empty(), "generated map {} has no spawn point", seed);
+         assert!(spawns.iter().all(|spawn| walkable[*spawn]), "generated map {} spawns on a blocked tile", seed);
+
+         // flood fill from the first spawn point
+         let mut reached = vec![false; walkable.len()];
+         let mut stack = vec![spawns[0]];
+         reached[spawns[0]] = true;
+         while let Some(index) = stack.pop() {
+            let (x, y) = (index % width, index / width);
+            let mut neighbours = vec![];
+            if x > 0 { neighbours.push(index - 1); }
+            if x + 1 < width { neighbours.push(index + 1); }
+            if y > 0 { neighbours.push(index - width); }
+            if index + width < walkable.len() { neighbours.push(index + width); }
+
+            for neighbour in neighbours {
+               if walkable[neighbour] && !reached[neighbour] {
+                  reached[neighbour] = true;
+                  stack.push(neighbour);
+               }
+            }
+         }
+
+         let unreachable = (0..walkable.len()).find(|index| walkable[*index] && !reached[*index]);
+         assert_eq!(unreachable, None, "generated map {} has unreachable grass", seed);
+      }
+   }
+
+   #[test]
+   fn size_depends_on_the_seed() {
+      let sizes: std::collections::HashSet<(usize, usize)> = (0..100)
+         .map(|seed| {
+            let enclosure = generate(seed);
+            (enclosure.width, enclosure.height)
+         })
+         .collect();
+
+      assert!(sizes.len() > 1);
+      for (width, height) in sizes {
+         assert!((MIN_WIDTH..=MAX_WIDTH).contains(&width) && (MIN_HEIGHT..=MAX_HEIGHT).contains(&height));
+      }
+   }
+
+   #[test]
+   fn same_seed_same_map() {
+      assert_eq!(generate(42).to_text(), generate(42).to_text());
+   }
+}
@@ -0,0 +1,76 @@
+use macroquad::prelude::*;
+
+use std::fs;
+use std::time::SystemTime;
+
+// reports files modified on disk, by polling their modification time
+pub struct FileWatcher {
+   // path and modification time when last seen, None if it could not be read
+   files: Vec<(String, Option<SystemTime>)>,
+   // seconds between two polls
+   interval: f64,
+   last_poll: f64
+}
+
+fn modified(path: &str) -> Option<SystemTime> {
+   fs::metadata(path).and_then(|metadata| metadata.modified()).ok()
+}
+
+impl FileWatcher {
+   pub fn new(interval: f64) -> FileWatcher {
+      FileWatcher {
+         files: Vec::new(),
+         interval,
+         last_poll: get_time()
+      }
+   }
+
+   pub fn watch(&mut self, path: &str) {
+      if self.files.iter().all(|(watched, _)| watched != path) {
+         self.files.push((path.to_string(), modified(path)));
+      }
+   }
+
+   // files modified since the last call, only checked every "interval" seconds
+   pub fn changed(&mut self) -> Vec<String> {
+      if get_time() - self.last_poll < self.interval {
+         return Vec::new();
+      }
+      self.last_poll = get_time();
+
+      let mut changed = Vec::new();
+      for (path, last_modified) in &mut self.files {
+         let modified = modified(path);
+         // files being written may briefly be missing, wait for them to come back
+         if modified.is_some() && modified != *last_modified {
+            *last_modified = modified;
+            changed.push(path.clone());
+         }
+      }
+      changed
+   }
+}
+
+// replace the pixels of "textures" with the image at "path".
+// Every copy of a texture handle shows the new pixels, but the size cannot change:
+// textures of another size keep their old pixels and are reported in the error.
+pub async fn reload_textures(path: &str, textures: &[Texture2D]) -> Result<(), String> {
+   let image = load_image(path).await.map_err(|error| error.to_string())?;
+
+   let size = (image.width() as f32, image.height() as f32);
+   let mut resized = Vec::new();
+   for texture in textures {
+      if size == (texture.width(), texture.height()) {
+         texture.update(&image);
+      } else {
+         resized.push(format!("{}x{}", texture.width(), texture.height()));
+      }
+   }
+
+   if resized.is_empty() {
+      Ok(())
+   } else {
+      Err(format!("the image is now {}x{}, {} texture(s) of {} kept their old pixels, restart to see it",
+         size.0, size.1, resized.len(), resized.join(", ")))
+   }
+}
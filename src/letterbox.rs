@@ -0,0 +1,49 @@
+use macroquad::prelude::*;
+
+// size of the rendered game in pixels, before it is scaled up to the window
+pub const GAME_WIDTH: u32 = 480;
+pub const GAME_HEIGHT: u32 = 270;
+
+// where the game is shown in the window
+#[derive(Clone, Copy, Debug)]
+pub struct Letterbox {
+   // in screen pixels, black bars fill the rest of the window
+   pub rect: Rect,
+   // screen pixels per game pixel
+   pub scale: f32
+}
+
+impl Letterbox {
+   // the game scaled by the largest whole factor fitting the window, centred,
+   // so pixels stay square and sharp. Windows smaller than the game show it shrunk to fit.
+   pub fn fit() -> Letterbox {
+      let screen = vec2(screen_width(), screen_height());
+      let game = vec2(GAME_WIDTH as f32, GAME_HEIGHT as f32);
+
+      let fit = (screen / game).min_element();
+      let scale = if fit >= 1.0 { fit.floor() } else { fit };
+      let size = game * scale;
+      let corner = ((screen - size) * 0.5).floor();
+
+      Letterbox {
+         rect: Rect::new(corner.x, corner.y, size.x, size.y),
+         scale
+      }
+   }
+
+   // screen position of the game pixel "pos"
+   pub fn to_screen(self, pos: Vec2) -> Vec2 {
+      self.rect.point() + pos * self.scale
+   }
+}
+
+// area of the world shown by the game camera for a map covering "map" world pixels.
+// Maps larger than the game keep the framing of the original enclosure,
+// smaller ones are centred.
+pub fn game_view(map: Rect) -> Rect {
+   let game = vec2(GAME_WIDTH as f32, GAME_HEIGHT as f32);
+   let centred = (map.point() + (map.size() - game) * 0.5).floor();
+   let x = if map.w < game.x { centred.x } else { 0.0 };
+   let y = if map.h < game.y { centred.y } else { 15.0 };
+   Rect::new(x, y, game.x, game.y)
+}
@@ -1,7 +1,17 @@
+mod animation;
+mod assets;
+mod editor;
+mod generator;
+mod hot_reload;
+mod letterbox;
 mod mover;
 mod panda_factory;
+mod post_process;
+mod render_queue;
+mod settings;
 mod stork_factory;
 mod tilemap;
+mod tuning;
 
 use macroquad::audio::Sound;
 use macroquad::audio::{self};
@@ -10,12 +20,31 @@ use macroquad_platformer::*;
 use macroquad::ui::{hash, root_ui, widgets::Window};
 // use macroquad_tiled as tiled;
 
+use animation::Animator;
+use assets::{AssetError, Assets};
+use editor::Editor;
+use hot_reload::FileWatcher;
+use letterbox::{Letterbox, GAME_HEIGHT, GAME_WIDTH};
 use mover::*;
 use panda_factory::*;
+use post_process::PostProcess;
+use render_queue::{RenderLayer, RenderQueue};
+use settings::{Settings, WindowMode};
 use stork_factory::*;
+use tuning::Tuning;
 
 use std::vec::Vec as Vector;
 
+const MANIFEST_PATH: &str = "assets/manifest.txt";
+const SETTINGS_PATH: &str = "settings.txt";
+const TUNING_PATH: &str = "assets/tuning.txt";
+// seconds between two checks for modified assets
+const HOT_RELOAD_INTERVAL: f64 = 0.5;
+// height of the middle of the hearts of pandas in love above their feet
+const HEART_HEIGHT: f32 = 33.0;
+// size of the score and bamboo texts, in game pixels
+const HUD_FONT_SIZE: f32 = 5.0;
+
 #[derive(PartialEq)]
 enum PlayerState {
     Normal,
@@ -25,12 +54,10 @@ enum PlayerState {
 
 struct Player {
     collider: Actor,
-    speed: f32,
     dir: Vec2,
     state: PlayerState,
     throw_cooldown: f32,
-    walk_anim_index: f32,
-    frame_countdown: f32,
+    animator: Animator,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
@@ -39,21 +66,59 @@ pub enum DrumFillEvent {
     Finish,
 }
 
+// window mode given by --windowed or --fullscreen, if any
+fn window_mode_arg() -> Option<WindowMode> {
+    std::env::args().find_map(|arg| {
+        arg.strip_prefix("--")
+            .and_then(WindowMode::from_name)
+    })
+}
+
 fn conf() -> Conf {
+    let window_mode = window_mode_arg().unwrap_or(Settings::load(SETTINGS_PATH).window_mode);
+
     Conf {
         window_title: String::from("Panda Date Arcade"),
-        window_width: 1920,
-        window_height: 1080,
-        fullscreen: true,
+        // three times the game size when windowed
+        window_width: GAME_WIDTH as i32 * 3,
+        window_height: GAME_HEIGHT as i32 * 3,
+        fullscreen: window_mode == WindowMode::Fullscreen,
+        window_resizable: true,
         ..Default::default()
     }
 }
 
-fn add_bamboo(bamboo_collection: &mut f32, bamboo_to_add: f32, bamboo_points: &mut Vec<Vec2>) {
+// seed of the map to generate, given by --seed or picked at random with --generate.
+// None to load the map of the asset manifest.
+fn map_seed() -> Option<u64> {
+    let args: Vec<String> = std::env::args().collect();
+
+    if let Some(ix) = args.iter().position(|arg| arg == "--seed") {
+        match args.get(ix + 1).map(|seed| seed.parse()) {
+            Some(Ok(seed)) => return Some(seed),
+            _ => eprintln!("--seed needs a positive number, generating a random map"),
+        }
+    } else if !args.iter().any(|arg| arg == "--generate") {
+        return None;
+    }
+
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default();
+    Some(now.as_nanos() as u64)
+}
+
+fn add_bamboo(
+    bamboo_collection: &mut f32,
+    bamboo_to_add: f32,
+    bamboo_points: &mut Vec<Vec2>,
+    tilemap: &tilemap::TileMap,
+    bamboo_size: Vec2,
+) {
     *bamboo_collection += bamboo_to_add;
 
     for _ in 0..bamboo_to_add as usize {
-        bamboo_points.push(get_random_game_point());
+        bamboo_points.push(get_random_bamboo_point(tilemap, bamboo_size));
     }
 }
 
@@ -73,100 +138,198 @@ fn remove_bamboo(
 
 #[macroquad::main(conf)]
 async fn main() {
+    let assets = match Assets::load(MANIFEST_PATH).await {
+        Ok(assets) => assets,
+        Err(error) => {
+            assets::error_screen(&error.to_string()).await;
+            return;
+        }
+    };
+
+    if let Err(error) = game(assets).await {
+        assets::error_screen(&error.to_string()).await;
+    }
+}
+
+async fn game(assets: Assets) -> Result<(), AssetError> {
     let mut player_score = 0;
     let mut elapsed_time = 0.0;
 
-    let track1 =
-        audio::load_sound("assets/Panda Dating Simulator - Turbo Arcade Edition Loop (127bpm).wav")
-            .await
-            .unwrap();
+    let track1 = assets.sound("music")?;
 
     play(&track1, true, 0.4);
 
-    let sfx_heart = audio::load_sound("assets/sfx_heart.wav").await.unwrap();
-    let sfx_impact = audio::load_sound("assets/sfx_impact.wav").await.unwrap();
-    let sfx_pickup = audio::load_sound("assets/sfx_pickup.wav").await.unwrap();
-    let sfx_throw = audio::load_sound("assets/sfx_throw.wav").await.unwrap();
+    let sfx_dead = assets.sound("sfx_dead")?;
+    let sfx_heart = assets.sound("sfx_heart")?;
+    let sfx_impact = assets.sound("sfx_impact")?;
+    let sfx_pickup = assets.sound("sfx_pickup")?;
+    let sfx_throw = assets.sound("sfx_throw")?;
     let mut sfx_loop_threshold = 0.0;
     let mut is_love_making = false;
 
-    let font = load_ttf_font("./assets/Gameplay.ttf").await.unwrap();
-
-    let panda_walking_texture = load_texture("assets/walking_panda-export2.png")
-        .await
-        .unwrap();
-    let panda_thrown_texture = load_texture("assets/thrown_panda-export2.png")
-        .await
-        .unwrap();
-    let panda_love_texture = load_texture("assets/dancing_panda-export2.png")
-        .await
-        .unwrap();
-    let panda_dead_texture = load_texture("assets/dead_panda.png").await.unwrap();
-
-    let stork_loaded_texture = load_texture("assets/stork_loaded.png").await.unwrap();
-    let stork_unloaded_texture = load_texture("assets/stork_unloaded.png").await.unwrap();
-
-    let player_walking_texture = load_texture("assets/walking_cupid_panda-export2.png")
-        .await
-        .unwrap();
-    let player_grabbing_texture = load_texture("assets/walking_cupid_panda_black-export2.png")
-        .await
-        .unwrap();
-
-    let heart_texture = load_texture("assets/heart.png").await.unwrap();
-
-    let mut total_bamboo = 100.0;
-    let bamboo_texture = load_texture("assets/bamboo.png").await.unwrap();
+    let font = assets.font("gameplay")?;
+
+    let panda_animations = PandaAnimations::load(&assets)?;
+
+    let stork_loaded_animation = assets.animation("stork_loaded")?;
+    let stork_unloaded_animation = assets.animation("stork_unloaded")?;
+
+    let player_walk_animation = assets.animation("player_walk")?;
+    let player_grab_animation = assets.animation("player_grab")?;
+
+    let mut settings = Settings::load(SETTINGS_PATH);
+    let mut post_process = PostProcess::new(&settings)?;
+    let mut settings_open = false;
+    let mut world = World::new();
+    let map_seed = map_seed();
+    let map_path = assets.map("enclosure")?;
+    let (tilemap, map_name) = match map_seed {
+        Some(seed) => {
+            println!("map seed: {}", seed);
+            let tilemap = generator::generate_tilemap(seed, &mut world).await;
+            (tilemap, format!("map seed {}", seed))
+        }
+        None => (
+            tilemap::load_tilemap(map_path, &mut world).await,
+            map_path.to_string(),
+        ),
+    };
+    let mut tilemap = match tilemap {
+        Ok(tilemap) => tilemap,
+        Err(error) => return Err(AssetError::Map { path: map_name, error }),
+    };
+    // generated maps are saved next to the map of the manifest, which they must not overwrite
+    let save_path = match map_seed {
+        Some(seed) => std::path::Path::new(map_path)
+            .with_file_name(format!("map_{}.txt", seed))
+            .to_string_lossy()
+            .into_owned(),
+        None => map_path.to_string(),
+    };
+    let mut editor = Editor::new(&save_path, std::env::args().any(|arg| arg == "--edit"));
+
+    let mut tuning = Tuning::load(TUNING_PATH);
+    let mut total_bamboo = tuning.start_bamboo;
+    let bamboo_texture = assets.texture("bamboo")?;
+    let mut render_queue = RenderQueue::new(assets.texture("shadow")?);
+    let bamboo_size = vec2(bamboo_texture.width(), bamboo_texture.height());
+
+    // textures are reloaded in place when modified on disk
+    let mut watcher = FileWatcher::new(HOT_RELOAD_INTERVAL);
+    for asset in assets.textures() {
+        watcher.watch(&asset.path);
+    }
+    for path in &tilemap.tile_paths {
+        watcher.watch(path);
+    }
+    if map_seed.is_none() {
+        watcher.watch(map_path);
+    }
+    watcher.watch(TUNING_PATH);
+    watcher.watch(SETTINGS_PATH);
     let mut bamboo_points = Vec::new();
     for _ in 0..total_bamboo as usize {
-        bamboo_points.push(get_random_game_point());
+        bamboo_points.push(get_random_bamboo_point(&tilemap, bamboo_size));
     }
 
-    let material =
-        load_material(CRT_VERTEX_SHADER, CRT_FRAGMENT_SHADER, Default::default()).unwrap();
-    let mut world = World::new();
-    let mut tilemap = tilemap::load_tilemap("assets/map.txt", &mut world).await;
-
-    const THROW_COOLDOWN: f32 = 2.0;
+    // the collider covers the hitbox of the walking sprite
+    let player_size = player_walk_animation.sheet.hitbox.size();
     let mut player = Player {
-        collider: world.add_actor(vec2(32.0, 150.0), 10, 10),
-        speed: 100.0,
+        collider: world.add_actor(
+            get_random_game_point(&tilemap, player_size),
+            player_size.x as i32,
+            player_size.y as i32,
+        ),
         dir: vec2(0.0, 0.0),
         state: PlayerState::Normal,
-        throw_cooldown: THROW_COOLDOWN,
-        walk_anim_index: 0.0,
-        frame_countdown: 0.05,
+        throw_cooldown: tuning.throw_cooldown,
+        animator: Animator::new(&player_walk_animation),
     };
 
     let mut pandas = Vector::<Panda>::new();
     let mut storks = Vector::<Stork>::new();
 
-    println!("w:{}, h:{}", screen_width(), screen_height());
-
-    pandas.push(PandaFactory::create_panda(&mut world));
-    pandas.push(PandaFactory::create_panda(&mut world));
 
-    let map_screen_width = 1920.0 / 4.0;
-    let mut camera =
-        Camera2D::from_display_rect(Rect::new(0.0, 15.0, map_screen_width, 1080.0 / 4.0));
-    let render_target = render_target(map_screen_width as u32, 1080 / 4);
+    pandas.push(PandaFactory::create_panda(&mut world, &tilemap, &panda_animations));
+    pandas.push(PandaFactory::create_panda(&mut world, &tilemap, &panda_animations));
 
-    const PANDA_LOVING_COOLDOWN_SECONDS: f32 = 3.0;
-    const PANDA_INDEPENDANT_DEATH_RATE_SECONDS: f64 = 20.0;
+    let render_target = render_target(GAME_WIDTH, GAME_HEIGHT);
+    render_target.texture.set_filter(FilterMode::Nearest);
 
     loop {
         if is_key_down(KeyCode::Escape) {
             break;
         }
 
+        // follows the size of the window
+        let letterbox = Letterbox::fit();
+
+        if is_key_pressed(KeyCode::F3) {
+            settings_open = !settings_open;
+        }
+
+        if is_key_pressed(KeyCode::F2) {
+            // pandas do not age while the game is paused
+            if let Some(paused) = editor.toggle() {
+                for panda in &mut pandas {
+                    panda.spawn_time += paused;
+                }
+            }
+        }
+
+        // hot reload
+        for path in watcher.changed() {
+            let result = if path == map_path {
+                let result = tilemap::reload_tilemap(map_path, &mut tilemap, &mut world).await;
+                for path in &tilemap.tile_paths {
+                    watcher.watch(path);
+                }
+                result.map_err(|error| error.to_string())
+            } else if path == TUNING_PATH {
+                tuning = Tuning::load(TUNING_PATH);
+                Ok(())
+            } else if path == SETTINGS_PATH {
+                // the window mode applies at the next start
+                settings = Settings::load(SETTINGS_PATH);
+                for (name, enabled) in &settings.post_process {
+                    post_process.set_enabled(name, *enabled);
+                }
+                Ok(())
+            } else {
+                // several tile types and assets may share an image
+                let mut textures: Vec<Texture2D> = tilemap
+                    .tile_paths
+                    .iter()
+                    .zip(&tilemap.tile_textures)
+                    .filter(|(tile_path, _)| **tile_path == path)
+                    .map(|(_, texture)| *texture)
+                    .collect();
+                if !textures.is_empty() {
+                    tilemap.invalidate();
+                }
+                textures.extend(assets.textures().filter(|asset| asset.path == path).map(|asset| asset.texture));
+                hot_reload::reload_textures(&path, &textures).await
+            };
+
+            match result {
+                Ok(()) => println!("reloaded {}", path),
+                Err(error) => eprintln!("could not reload {}: {}", path, error),
+            }
+        }
+
         let delta_time = get_frame_time();
-        elapsed_time += delta_time;
+        if !editor.active {
+            elapsed_time += delta_time;
+        }
 
+        // generated and reloaded maps may have another size
+        let view = letterbox::game_view(tilemap.bounds());
+        let mut camera = Camera2D::from_display_rect(view);
         camera.render_target = Some(render_target);
         set_camera(&camera);
 
         // draw map
-        tilemap.draw();
+        tilemap.draw_ground();
 
         for bam in &bamboo_points {
             draw_texture_ex(
@@ -183,103 +346,68 @@ async fn main() {
         //   panda_spawn_countdown -= delta_time;
 
         //   if panda_spawn_countdown <= 0.0 {
-        //      panda_spawn_countdown = PANDA_LOVING_COOLDOWN_SECONDS;
-        //      pandas.push(PandaFactory::create_panda(&mut world));
+        //      panda_spawn_countdown = tuning.love_cooldown;
+        //      pandas.push(PandaFactory::create_panda(&mut world, &tilemap, &panda_animations));
         //   }
 
         is_love_making = false;
 
-        // draw pandas
+        // queue player
+        let player_anchor = animation::actor_anchor(world.actor_rect(player.collider));
         {
-            for panda in &pandas {
-                let pos = world.actor_pos(panda.collider);
-                if panda.state == PandaState::Dead {
-                    draw_texture_ex(
-                        panda_dead_texture,
-                        pos.x - 12.0,
-                        pos.y - 15.0,
-                        WHITE,
-                        DrawTextureParams {
-                            ..Default::default()
-                        },
-                    );
-                    continue;
-                }
+            let animation = if player.state == PlayerState::Grabbing {
+                &player_grab_animation
+            } else {
+                &player_walk_animation
+            };
+            player.animator.play(animation);
+            render_queue.push_shadow(player_anchor, player.animator.sheet().hitbox.w, 0.0);
+            render_queue.push(
+                RenderLayer::Ground,
+                player_anchor.y,
+                player.animator.sprite(player_anchor, WHITE, Default::default()),
+            );
+        }
 
-                if panda.state == PandaState::Thrown {
-                    draw_texture_ex(
-                        panda_thrown_texture,
-                        pos.x - 5.0,
-                        pos.y - 15.0,
-                        WHITE,
-                        DrawTextureParams {
-                            dest_size: Some(vec2(32.0, 32.0)),
-                            source: Some(Rect::new(
-                                32.0 * panda.thrown_anim_index,
-                                0.0,
-                                32.0,
-                                32.0,
-                            )),
-                            ..Default::default()
-                        },
-                    );
-                } else if panda.state == PandaState::Grabbed {
+        // queue pandas
+        {
+            for panda in &mut pandas {
+                let anchor = animation::actor_anchor(world.actor_rect(panda.collider));
+                panda.animator.play(panda_animations.for_state(&panda.state));
+
+                if panda.state == PandaState::Grabbed {
                     let ms = macroquad::time::get_time() * 1000.0;
                     if ms as u64 % 2 == 0 {
-                        draw_texture_ex(
-                            panda_walking_texture,
-                            pos.x - 14.0,
-                            pos.y - 30.0,
+                        // held in front of the player
+                        let sprite = panda.animator.sprite(
+                            anchor,
                             WHITE,
                             DrawTextureParams {
-                                dest_size: Some(vec2(32.0, 32.0)),
-                                source: Some(Rect::new(
-                                    32.0 * panda.walk_anim_index,
-                                    0.0,
-                                    32.0,
-                                    32.0,
-                                )),
-                                rotation: 0.5 * 3.14,
+                                rotation: std::f32::consts::FRAC_PI_2,
                                 ..Default::default()
                             },
                         );
+                        render_queue.push(RenderLayer::Ground, player_anchor.y, sprite);
                     }
-                } else if panda.state == PandaState::FoundLove {
-                    is_love_making = true;
-                    draw_texture_ex(
-                        panda_love_texture,
-                        pos.x - 12.0,
-                        pos.y - 15.0,
-                        WHITE,
-                        DrawTextureParams {
-                            dest_size: Some(vec2(32.0, 32.0)),
-                            source: Some(Rect::new(32.0 * panda.love_anim_index, 0.0, 32.0, 32.0)),
-                            ..Default::default()
-                        },
-                    );
+                    continue;
+                }
 
-                    draw_texture_ex(
-                        heart_texture,
-                        pos.x - 8.0,
-                        pos.y - 25.0,
-                        WHITE,
-                        DrawTextureParams {
-                            dest_size: Some(vec2(16.0, 16.0)),
-                            source: Some(Rect::new(16.0 * panda.heart_anim_index, 0.0, 16.0, 16.0)),
-                            ..Default::default()
-                        },
-                    );
-                } else {
-                    draw_texture_ex(
-                        panda_walking_texture,
-                        pos.x - 12.0,
-                        pos.y - 15.0,
-                        WHITE,
-                        DrawTextureParams {
-                            dest_size: Some(vec2(32.0, 32.0)),
-                            source: Some(Rect::new(32.0 * panda.walk_anim_index, 0.0, 32.0, 32.0)),
-                            ..Default::default()
-                        },
+                // thrown pandas fly above their shadow
+                let height = panda.height();
+                render_queue.push_shadow(anchor, panda.animator.sheet().hitbox.w, height);
+                render_queue.push(
+                    RenderLayer::Ground,
+                    anchor.y,
+                    panda.animator.sprite(anchor - vec2(0.0, height), WHITE, Default::default()),
+                );
+
+                if panda.state == PandaState::FoundLove {
+                    is_love_making = true;
+                    let heart_anchor = anchor - vec2(0.0, HEART_HEIGHT);
+                    render_queue.push(
+                        RenderLayer::Ground,
+                        anchor.y,
+                        panda.heart_animator.sprite(heart_anchor, WHITE, Default::default()),
                     );
                 }
             }
@@ -293,66 +421,52 @@ async fn main() {
             .filter(|p| p.state != PandaState::Dead)
             .count();
 
-            if alive_pandas <= 1 {
+            if alive_pandas <= 1 && !editor.active {
               if game_over(false) {
-                return;
+                return Ok(());
               }
             }
         }
 
-        // draw storks
+        // queue storks
         {
             for stork in &mut storks {
-                if stork.state == StorkState::Loaded {
-                    draw_texture_ex(
-                        stork_loaded_texture,
-                        stork.pos.x,
-                        stork.pos.y,
-                        WHITE,
-                        DrawTextureParams {
-                            flip_x: stork.speed.x < 0.0,
-                            dest_size: Some(vec2(32.0, 32.0)),
-                            source: Some(Rect::new(32.0 * stork.anim_index, 0.0, 32.0, 32.0)),
-                            ..Default::default()
-                        },
-                    );
+                let animation = if stork.state == StorkState::Loaded {
+                    &stork_loaded_animation
                 } else {
-                    draw_texture_ex(
-                        stork_unloaded_texture,
-                        stork.pos.x,
-                        stork.pos.y,
-                        WHITE,
-                        DrawTextureParams {
-                            flip_x: stork.speed.x < 0.0,
-                            dest_size: Some(vec2(32.0, 32.0)),
-                            source: Some(Rect::new(32.0 * stork.anim_index, 0.0, 32.0, 32.0)),
-                            ..Default::default()
-                        },
-                    );
-                }
+                    &stork_unloaded_animation
+                };
+                stork.animator.play(animation);
+                render_queue.push_shadow(stork.pos, stork.animator.sheet().hitbox.w, STORK_ALTITUDE);
+                let sprite = stork.animator.sprite(
+                    stork.pos - vec2(0.0, STORK_ALTITUDE),
+                    WHITE,
+                    DrawTextureParams {
+                        flip_x: stork.speed.x < 0.0,
+                        ..Default::default()
+                    },
+                );
+                render_queue.push(RenderLayer::Air, stork.pos.y, sprite);
             }
         }
 
-        // draw player
-        {
-            // sprite id from tiled
-            let pos = world.actor_pos(player.collider);
-            let texture = if player.state == PlayerState::Grabbing {
-                player_grabbing_texture
-            } else {
-                player_walking_texture
-            };
-            draw_texture_ex(
-                texture,
-                pos.x - 12.0,
-                pos.y - 15.0,
-                WHITE,
-                DrawTextureParams {
-                    dest_size: Some(vec2(32.0, 32.0)),
-                    source: Some(Rect::new(32.0 * player.walk_anim_index, 0.0, 32.0, 32.0)),
-                    ..Default::default()
-                },
-            );
+        // draw pandas, storks and the player from back to front
+        render_queue.draw();
+
+        // draw map layers above the actors
+        tilemap.draw_overlay();
+
+        // the editor pauses the simulation
+        if editor.active {
+            editor.update(&mut tilemap, &mut world, view, letterbox.rect);
+            editor.draw_world(&tilemap, view, letterbox.rect);
+            post_process.draw(render_target.texture, letterbox.rect);
+            editor.draw_ui(&tilemap);
+            if settings_open {
+                settings_open = settings_window(&mut post_process, &mut settings);
+            }
+            next_frame().await;
+            continue;
         }
 
         // player control
@@ -380,15 +494,15 @@ async fn main() {
 
             let diag_move = player.dir.x != 0.0 && player.dir.y != 0.0;
             let x_speed = if diag_move {
-                (1.0 / (2.0 as f32).sqrt() * player.dir.x) * player.speed
+                (1.0 / (2.0 as f32).sqrt() * player.dir.x) * tuning.player_speed
             } else {
-                player.dir.x * player.speed
+                player.dir.x * tuning.player_speed
             };
 
             let y_speed = if diag_move {
-                (1.0 / (2.0 as f32).sqrt() * player.dir.y) * player.speed
+                (1.0 / (2.0 as f32).sqrt() * player.dir.y) * tuning.player_speed
             } else {
-                player.dir.y * player.speed
+                player.dir.y * tuning.player_speed
             };
 
             if movement_is_happening {
@@ -400,7 +514,7 @@ async fn main() {
                 player.throw_cooldown -= delta_time;
 
                 if player.throw_cooldown < 0.0 {
-                    player.throw_cooldown = THROW_COOLDOWN;
+                    player.throw_cooldown = tuning.throw_cooldown;
                     player.state = PlayerState::Normal;
                 }
             }
@@ -417,7 +531,7 @@ async fn main() {
                     if is_key_pressed(KeyCode::Space) {
                         player.state = PlayerState::Throwing;
                         panda.state = PandaState::Thrown;
-                        panda.mover = Box::new(ThrownMover::new(player.dir));
+                        panda.mover = Box::new(ThrownMover::new(player.dir, tilemap.bounds()));
                         play(&sfx_throw, false, 0.8);
                     } else {
                         let player_pos = world.actor_pos(player.collider);
@@ -433,12 +547,28 @@ async fn main() {
                         let speed_x = rand::gen_range(0.0, 50.0);
                         let speed_y = rand::gen_range(0.0, 50.0);
                         panda.speed = vec2(speed_x, speed_y);
-                        panda.sweet_panda_loving_cooldown = PANDA_LOVING_COOLDOWN_SECONDS;
+                        panda.sweet_panda_loving_cooldown = tuning.love_cooldown;
                     }
                 } else {
                     panda.apply_movement(&mut world);
 
                     if panda.mover.movement_complete() {
+                        if panda.state == PandaState::Thrown {
+                            let collider = world.actor_rect(panda.collider);
+                            if tilemap.is_water(animation::actor_anchor(collider)) {
+                                play(&sfx_dead, false, 0.8);
+                                panda.state = PandaState::Dead;
+                                continue;
+                            }
+                            play(&sfx_impact, false, 0.8);
+
+                            // landed partly in the water, step onto the shore
+                            let radius = tilemap.tile_size().x * 2.0;
+                            if let Some(pos) = tilemap.nearest_walkable_point(collider, radius) {
+                                world.set_actor_position(panda.collider, pos);
+                            }
+                        }
+
                         panda.state = PandaState::Normal;
                         panda.mover = Box::new(NormalMover::new());
 
@@ -454,10 +584,10 @@ async fn main() {
         {
             for stork in &mut storks {
                 if stork.apply_movement(get_frame_time()) {
-                    pandas.push(PandaFactory::create_panda_at(&mut world, stork.pos));
+                    pandas.push(PandaFactory::create_panda_at(&mut world, stork.pos, &panda_animations));
                 }
 
-                stork.update_animation(get_frame_time());
+                stork.animator.update(get_frame_time());
             }
         }
 
@@ -472,9 +602,8 @@ async fn main() {
                 let player_pos = world.actor_pos(player.collider);
                 let panda_pos = world.actor_pos(panda.collider);
 
-                const GRAB_RANGE: f32 = 20.0;
-                if (player_pos.x - panda_pos.x).abs() < GRAB_RANGE
-                    && (player_pos.y - panda_pos.y).abs() < GRAB_RANGE
+                if (player_pos.x - panda_pos.x).abs() < tuning.grab_range
+                    && (player_pos.y - panda_pos.y).abs() < tuning.grab_range
                     && is_key_pressed(KeyCode::Space)
                     && panda.state != PandaState::Grabbed
                     && player.state == PlayerState::Normal
@@ -513,17 +642,17 @@ async fn main() {
 
                     let second_panda_pos = world.actor_pos(second_panda.collider);
 
-                    const HUBBA_HUBBA_RANGE: f32 = 32.0;
 
                     let val = (first_panda_pos.x - second_panda_pos.x).abs();
                     let val2 = (first_panda_pos.y - second_panda_pos.y).abs();
 
-                    if val < HUBBA_HUBBA_RANGE && val2 < HUBBA_HUBBA_RANGE {
+                    if val < tuning.love_range && val2 < tuning.love_range {
                         in_love_indices.push(first_panda_index);
                         in_love_indices.push(second_panda_index);
                         storks.push(StorkFactory::create_stork(
                             first_panda_pos,
-                            map_screen_width,
+                            tilemap.bounds().w,
+                            &stork_loaded_animation,
                         ));
                         player_score += 50;
                         break;
@@ -544,7 +673,7 @@ async fn main() {
                     continue;
                 }
 
-                if get_time() - p.spawn_time > PANDA_INDEPENDANT_DEATH_RATE_SECONDS {
+                if get_time() - p.spawn_time > tuning.panda_lifetime as f64 {
                     p.state = PandaState::Dead;
                 }
             }
@@ -559,7 +688,7 @@ async fn main() {
                 total_bamboo = 0.0; 
                 
                 if game_over(true) {
-                   return;
+                   return Ok(());
                 }
             } else {
                 let hungry_pandas = pandas
@@ -568,18 +697,21 @@ async fn main() {
                     .filter(|p| p.state == PandaState::Normal)
                     .count();
 
-                const HUNGER_RATE: f32 = 0.25;
-                let eaten_bamboo = hungry_pandas as f32 * (HUNGER_RATE * delta_time);
+                let eaten_bamboo = hungry_pandas as f32 * (tuning.hunger_rate * delta_time);
                 remove_bamboo(&mut total_bamboo, eaten_bamboo, &mut bamboo_points);
 
                 total_bamboo -= eaten_bamboo;
             }
 
-            const BAMBOO_REFRESH_TIME_SECONDS: f32 = 10.0;
-            const BAMBOO_TO_ADD: f32 = 10.0;
-            if elapsed_time > BAMBOO_REFRESH_TIME_SECONDS {
+            if elapsed_time > tuning.bamboo_interval {
                 elapsed_time = 0.0;
-                add_bamboo(&mut total_bamboo, BAMBOO_TO_ADD, &mut bamboo_points)
+                add_bamboo(
+                    &mut total_bamboo,
+                    tuning.bamboo_delivery,
+                    &mut bamboo_points,
+                    &tilemap,
+                    bamboo_size,
+                )
             }
         }
 
@@ -593,24 +725,13 @@ async fn main() {
                         p.sweet_panda_loving_cooldown = 0.0;
                     }
 
-                    p.frame_countdown -= delta_time;
-
-                    if p.frame_countdown <= 0.0 {
-                        p.update_animation_indices();
-                    }
                 }
-            }
 
-            player.frame_countdown -= delta_time;
-
-            if player.frame_countdown <= 0.0 {
-                player.frame_countdown = 0.25;
-                player.walk_anim_index += 1.0;
-
-                if player.walk_anim_index == 4.0 {
-                    player.walk_anim_index = 0.0;
-                }
+                p.animator.update(delta_time);
+                p.heart_animator.update(delta_time);
             }
+
+            player.animator.update(delta_time);
         }
 
         // SFX looping
@@ -624,30 +745,16 @@ async fn main() {
             }
         }
 
-        set_default_camera();
-        clear_background(GREEN);
-        render_target.texture.set_filter(FilterMode::Nearest);
-        gl_use_material(material);
-        draw_texture_ex(
-            render_target.texture,
-            0.,
-            0.,
-            WHITE,
-            DrawTextureParams {
-                dest_size: Some(vec2(screen_width(), screen_height())),
-                flip_y: true,
-                ..Default::default()
-            },
-        );
-        gl_use_default_material();
+        post_process.draw(render_target.texture, letterbox.rect);
 
         let text = format!("Remaining Bamboo: {}", total_bamboo as i32);
+        let text_pos = letterbox.to_screen(vec2(5.0, 7.5));
         draw_text_ex(
             &text,
-            20.0,
-            30.0,
+            text_pos.x,
+            text_pos.y,
             TextParams {
-                font_size: 20,
+                font_size: (HUD_FONT_SIZE * letterbox.scale) as u16,
                 color: RED,
                 font,
                 ..Default::default()
@@ -655,21 +762,72 @@ async fn main() {
         );
 
         let score_text = format!("Score: {}", player_score as i32);
+        let score_pos = letterbox.to_screen(vec2(5.0, 15.0));
 
         draw_text_ex(
             &score_text,
-            20.0,
-            60.0,
+            score_pos.x,
+            score_pos.y,
             TextParams {
-                font_size: 20,
+                font_size: (HUD_FONT_SIZE * letterbox.scale) as u16,
                 color: RED,
                 font,
                 ..Default::default()
             },
         );
 
+        if settings_open {
+            settings_open = settings_window(&mut post_process, &mut settings);
+        }
+
         next_frame().await
     }
+
+    Ok(())
+}
+
+// window toggling the post-process passes and picking the window mode of the next
+// start, saved to SETTINGS_PATH when changed. Returns false once closed.
+fn settings_window(post_process: &mut PostProcess, settings: &mut Settings) -> bool {
+   let dialog_size = vec2(300., 240.);
+   let dialog_position = vec2(screen_width(), screen_height()) / 2. - dialog_size / 2.;
+   let mut passes: Vec<(&str, bool)> = post_process.passes().collect();
+   let mut open = true;
+   let mut window_mode = settings.window_mode;
+
+   Window::new(hash!(), dialog_position, dialog_size).ui(&mut root_ui(), |ui| {
+       ui.label(None, "Screen effects");
+       for (name, enabled) in &mut passes {
+          ui.checkbox(hash!(*name), &name.replace('_', " "), enabled);
+       }
+       ui.separator();
+       if ui.button(None, format!("Window: {}", window_mode.name()).as_str()) {
+          window_mode = window_mode.next();
+       }
+       ui.label(None, "(applied at the next start)");
+       ui.separator();
+       if ui.button(None, "Close [F3]") {
+          open = false;
+       }
+   });
+
+   let mut changed = window_mode != settings.window_mode;
+   settings.window_mode = window_mode;
+   for (name, enabled) in passes {
+      if post_process.passes().any(|pass| pass == (name, !enabled)) {
+         post_process.set_enabled(name, enabled);
+         settings.set_post_process(name, enabled);
+         changed = true;
+      }
+   }
+
+   if changed {
+      if let Err(error) = settings.save(SETTINGS_PATH) {
+         eprintln!("could not save {}: {}", SETTINGS_PATH, error);
+      }
+   }
+
+   open
 }
 
 fn game_over(ran_out_of_bamboo: bool) -> bool {
@@ -685,7 +843,7 @@ fn game_over(ran_out_of_bamboo: bool) -> bool {
    let dialog_position = screen_size / 2. - dialog_size / 2.;
    let mut user_response = false;
    Window::new(hash!(), dialog_position, dialog_size).ui(&mut *root_ui(), |ui| {
-       ui.label(None, &msg);
+       ui.label(None, msg);
        ui.separator();
        ui.same_line(480.);
        if ui.button(None, "Goodbye") {
@@ -697,10 +855,19 @@ fn game_over(ran_out_of_bamboo: bool) -> bool {
 }
 
 
-fn get_random_game_point() -> Vec2 {
-    let x: f32 = macroquad::rand::gen_range(16.0, 480.0 - 32.0 - 16.0);
-    let y: f32 = macroquad::rand::gen_range(16.0, 270.0 - 16.0 - 16.0);
-    vec2(x.floor(), y.floor())
+// top left corner of something of "size" pixels at a random walkable place,
+// the middle of the map if there is none
+fn get_random_game_point(tilemap: &tilemap::TileMap, size: Vec2) -> Vec2 {
+    let bounds = tilemap.bounds();
+    tilemap
+        .random_walkable_point(size)
+        .unwrap_or_else(|| (bounds.point() + (bounds.size() - size) * 0.5).floor())
+}
+
+fn get_random_bamboo_point(tilemap: &tilemap::TileMap, size: Vec2) -> Vec2 {
+    tilemap
+        .random_bamboo_point(size)
+        .unwrap_or_else(|| get_random_game_point(tilemap, size))
 }
 
 fn play(sound: &Sound, looped: bool, volume: f32) {
@@ -713,73 +880,3 @@ fn play(sound: &Sound, looped: bool, volume: f32) {
         },
     );
 }
-
-const CRT_FRAGMENT_SHADER: &'static str = r#"#version 100
-precision lowp float;
-
-varying vec4 color;
-varying vec2 uv;
-    
-uniform sampler2D Texture;
-
-// https://www.shadertoy.com/view/XtlSD7
-
-vec2 CRTCurveUV(vec2 uv)
-{
-    uv = uv * 2.0 - 1.0;
-    vec2 offset = abs( uv.yx ) / vec2( 12.0, 4.0 );
-    uv = uv + uv * offset * offset;
-    uv = uv * 0.5 + 0.5;
-    return uv;
-}
-
-void DrawVignette( inout vec3 color, vec2 uv )
-{    
-    float vignette = uv.x * uv.y * ( 1.0 - uv.x ) * ( 1.0 - uv.y );
-    vignette = clamp( pow( 50.0 * vignette, 0.2 ), 0.0, 1.0 );
-    color *= vignette * 1.0;
-}
-
-
-void DrawScanline( inout vec3 color, vec2 uv )
-{
-    float iTime = 0.9;
-    float scanline 	= clamp( 0.95 + 0.05 * cos( 3.14 * ( uv.y + 0.008 * iTime ) * 1080.0 * 0.4 ), 0.0, 1.0 );
-    float grille 	= 1.0 + 0.15 * clamp( 1.0 * cos( 3.14159265359 * uv.x * 1920.0 * 0.4 ), 0.2, 1.0 );    
-    color *= scanline * grille * 1.1;
-}
-
-void main() {
-    
-    vec2 crtUV = CRTCurveUV(uv);
-    
-    vec3 res = texture2D(Texture, uv).rgb * color.rgb;
- 	
-    if (crtUV.x < 0.0 || crtUV.x > 1.0 || crtUV.y < 0.0 || crtUV.y > 1.0)
-    {
-        res = vec3(0.0, 0.0, 0.0);
-    } 
-    DrawVignette(res, crtUV);
-    DrawScanline(res, uv);
-    gl_FragColor = vec4(res, 1.0);
-
-}
-"#;
-
-const CRT_VERTEX_SHADER: &'static str = "#version 100
-attribute vec3 position;
-attribute vec2 texcoord;
-attribute vec4 color0;
-
-varying lowp vec2 uv;
-varying lowp vec4 color;
-
-uniform mat4 Model;
-uniform mat4 Projection;
-
-void main() {
-    gl_Position = Projection * Model * vec4(position, 1);
-    color = color0 / 255.0;
-    uv = texcoord;
-}
-";
@@ -1,9 +1,16 @@
 use macroquad::prelude::*;
 use macroquad_platformer::*;
 
+use crate::tilemap::AIR_TAG;
+
 pub trait Mover {
    fn apply_movement_routine(&mut self, world: &mut World, collider: &Actor, speed: &mut Vec2);
    fn movement_complete(&self) -> bool;
+
+   // pixels above the ground
+   fn height(&self) -> f32 {
+      0.0
+   }
 }
 
 pub struct NormalMover {
@@ -43,20 +50,35 @@ impl Mover for NormalMover {
    }
 }
 
+// highest point of a throw in pixels above the ground, and time spent in the air in seconds
+const THROW_HEIGHT: f32 = 20.0;
+const THROW_AIRTIME: f32 = 0.6;
+
+// flies over water until it lands, THROW_AIRTIME seconds later, or earlier
+// when it hits a rock or the edge of the map
 pub struct ThrownMover {
    thrown_direction: Vec2,
+   // area of the map, in world pixels
+   bounds: Rect,
    throwing_speed: f32,
-   init_time: f64
+   init_time: f64,
+   height: f32,
+   vertical_speed: f32,
+   landed: bool
 }
 
 impl ThrownMover {
-   pub fn new(dir: Vec2) -> Self {
+   pub fn new(dir: Vec2, bounds: Rect) -> Self {
       const THROWING_SPEED: f32 = 500.0;
 
       ThrownMover{
          thrown_direction: dir,
+         bounds,
          throwing_speed: THROWING_SPEED,
          init_time: get_time(),
+         height: 0.0,
+         vertical_speed: 4.0 * THROW_HEIGHT / THROW_AIRTIME,
+         landed: false
       }
    }
 }
@@ -79,8 +101,22 @@ impl Mover for ThrownMover {
       self.thrown_direction.x = self.thrown_direction.x / numerator;
       self.thrown_direction.y = self.thrown_direction.y / numerator;
 
-      world.move_h(*collider, (self.thrown_direction.x * self.throwing_speed) * get_frame_time());
-      world.move_v(*collider, (self.thrown_direction.y * self.throwing_speed) * get_frame_time());
+      // in the air, so water does not stop it. Rocks and the edges of the map make it land early.
+      let rect = world.actor_rect(*collider);
+      let pos = rect.point() + self.thrown_direction * self.throwing_speed * get_frame_time();
+      let inside = pos.clamp(self.bounds.point(), self.bounds.point() + self.bounds.size() - rect.size());
+      let hits_rock = world.collide_tag(AIR_TAG, inside, rect.w as i32, rect.h as i32) != Tile::Empty;
+      if !hits_rock {
+         world.set_actor_position(*collider, inside);
+      }
+
+      const GRAVITY: f32 = 8.0 * THROW_HEIGHT / (THROW_AIRTIME * THROW_AIRTIME);
+      self.vertical_speed -= GRAVITY * get_frame_time();
+      self.height += self.vertical_speed * get_frame_time();
+      if self.height <= 0.0 || hits_rock || inside != pos {
+         self.height = 0.0;
+         self.landed = true;
+      }
 
       let time_delta = (get_time() - self.init_time) * 0.869;
       let decay_rate = 1.0 - (time_delta).powf(2.0);
@@ -89,7 +125,11 @@ impl Mover for ThrownMover {
    }
 
    fn movement_complete(&self) -> bool {
-      self.throwing_speed == 0.0
+      self.landed
+   }
+
+   fn height(&self) -> f32 {
+      self.height
    }
 }
 
@@ -1,6 +1,9 @@
 use macroquad::prelude::*;
 use macroquad_platformer::*;
+use crate::animation::{Animation, Animator};
+use crate::assets::{AssetError, Assets};
 use crate::mover::*;
+use crate::tilemap::TileMap;
 
 #[derive(PartialEq)]
 pub enum PandaState {
@@ -16,11 +19,9 @@ pub struct Panda {
    pub speed: Vec2,
    pub mover: Box<dyn Mover>,
    pub state: PandaState,
-   pub heart_anim_index: f32,
-   pub walk_anim_index: f32,
-   pub love_anim_index: f32,
-   pub thrown_anim_index: f32,
-   pub frame_countdown: f32,
+   pub animator: Animator,
+   // hearts shown above pandas in love
+   pub heart_animator: Animator,
    pub spawn_time: f64,
    pub sweet_panda_loving_cooldown: f32
 }
@@ -32,62 +33,73 @@ impl Panda {
          .apply_movement_routine(world, &self.collider, &mut self.speed)
    }
 
-   pub fn update_animation_indices(&mut self) {
-      self.frame_countdown = 0.1;
-      self.heart_anim_index += 1.0;
-      self.walk_anim_index += 1.0;
-      self.love_anim_index += 1.0;
-      self.thrown_anim_index += 1.0;
-
-      if self.heart_anim_index == 4.0 {
-         self.heart_anim_index = 0.0;
-      }
+   // pixels above the ground, while thrown
+   pub fn height(&self) -> f32 {
+      self.mover.height()
+   }
+}
 
-      if self.walk_anim_index == 4.0 {
-         self.walk_anim_index = 0.0;
-      }
+// animations of the panda states, from the asset manifest
+pub struct PandaAnimations {
+   pub walk: Animation,
+   pub thrown: Animation,
+   pub love: Animation,
+   pub dead: Animation,
+   pub heart: Animation
+}
 
-      if self.love_anim_index == 9.0 {
-         self.love_anim_index = 0.0;
-      }
+impl PandaAnimations {
+   pub fn load(assets: &Assets) -> Result<PandaAnimations, AssetError> {
+      Ok(PandaAnimations {
+         walk: assets.animation("panda_walk")?,
+         thrown: assets.animation("panda_thrown")?,
+         love: assets.animation("panda_love")?,
+         dead: assets.animation("panda_dead")?,
+         heart: assets.animation("heart")?
+      })
+   }
 
-      if self.thrown_anim_index == 2.0 {
-         self.thrown_anim_index = 0.0;
+   pub fn for_state(&self, state: &PandaState) -> &Animation {
+      match state {
+         PandaState::Normal | PandaState::Grabbed => &self.walk,
+         PandaState::Thrown => &self.thrown,
+         PandaState::FoundLove => &self.love,
+         PandaState::Dead => &self.dead,
       }
    }
 }
 
-
 pub struct PandaFactory {
    
 }
 
 impl PandaFactory {
-   pub fn create_panda(world: &mut World) -> Panda {
-      
-      let spawn_points = vec![vec2(170.0, 230.0), vec2(200.0, 100.0), vec2(230.0, 170.0), vec2(100.0, 200.0)];
-      let spawn_index = rand::gen_range(0, spawn_points.len()) as usize;
+   // a panda at a random spawn point of the map
+   pub fn create_panda(world: &mut World, tilemap: &TileMap, animations: &PandaAnimations) -> Panda {
+      let size = animations.walk.sheet.hitbox.size();
+      let bounds = tilemap.bounds();
+      let pos = tilemap.random_spawn_point(size)
+         .unwrap_or(bounds.point() + (bounds.size() - size) * 0.5);
    
-      return PandaFactory::create_panda_at(world, spawn_points[spawn_index]);
+      return PandaFactory::create_panda_at(world, pos, animations);
    }
 
-   pub fn create_panda_at(world: &mut World, pos: Vec2) -> Panda {
+   pub fn create_panda_at(world: &mut World, pos: Vec2, animations: &PandaAnimations) -> Panda {
    
       let speed_x = rand::gen_range(0.0, 50.0);
       let speed_y = rand::gen_range(0.0, 50.0);
 
       let rounded_pos = Vec2::new(pos.x.round(), pos.y.round());
+      // the collider covers the hitbox of the walking sprite
+      let size = animations.walk.sheet.hitbox.size();
       
       Panda {
-         collider: world.add_actor(rounded_pos, 16, 16),
+         collider: world.add_actor(rounded_pos, size.x as i32, size.y as i32),
          speed: vec2(speed_x, speed_y),
          mover: Box::new(NormalMover::new()),
          state: PandaState::Normal,
-         heart_anim_index: 0.0,
-         walk_anim_index: 0.0,
-         love_anim_index: 0.0,
-         thrown_anim_index: 0.0,
-         frame_countdown: 0.05,
+         animator: Animator::new(&animations.walk),
+         heart_animator: Animator::new(&animations.heart),
          spawn_time: get_time(),
          sweet_panda_loving_cooldown: 0.0
       }
@@ -0,0 +1,262 @@
+use macroquad::prelude::*;
+
+use crate::assets::AssetError;
+use crate::settings::Settings;
+
+// a full screen effect applied to the rendered game
+struct Pass {
+   name: &'static str,
+   material: Material,
+   enabled: bool
+}
+
+// Passes applied in this order: name, fragment shader main function and whether
+// they are enabled when the settings do not say.
+const PASSES: &[(&str, &str, bool)] = &[
+   ("bloom", BLOOM_SHADER, false),
+   ("color_grading", COLOR_GRADING_SHADER, false),
+   ("curvature", CURVATURE_SHADER, true),
+   ("scanlines", SCANLINES_SHADER, true),
+   ("vignette", VIGNETTE_SHADER, true),
+];
+
+// chain of post-process passes drawing the game to the screen
+pub struct PostProcess {
+   passes: Vec<Pass>,
+   // images between two passes, at the size the game is shown at
+   targets: Vec<RenderTarget>,
+   target_size: (u32, u32)
+}
+
+impl PostProcess {
+   pub fn new(settings: &Settings) -> Result<PostProcess, AssetError> {
+      let mut passes = Vec::new();
+      for (name, shader, enabled) in PASSES {
+         let material = load_material(VERTEX_SHADER,
+            &format!("{}{}", FRAGMENT_SHADER_HEADER, shader),
+            MaterialParams {
+               uniforms: vec![
+                  ("Time".to_string(), UniformType::Float1),
+                  ("Resolution".to_string(), UniformType::Float2),
+               ],
+               ..Default::default()
+            })
+            .map_err(|error| AssetError::Load {
+               path: format!("{} shader", name),
+               error: format!("{:?}", error)
+            })?;
+
+         passes.push(Pass {
+            name,
+            material,
+            enabled: settings.post_process(name).unwrap_or(*enabled)
+         });
+      }
+
+      Ok(PostProcess {
+         passes,
+         targets: Vec::new(),
+         target_size: (0, 0)
+      })
+   }
+
+   // names of the passes in the order they are applied, with whether they are enabled
+   pub fn passes(&self) -> impl Iterator<Item = (&'static str, bool)> + '_ {
+      self.passes.iter().map(|pass| (pass.name, pass.enabled))
+   }
+
+   pub fn set_enabled(&mut self, name: &str, enabled: bool) {
+      if let Some(pass) = self.passes.iter_mut().find(|pass| pass.name == name) {
+         pass.enabled = enabled;
+      }
+   }
+
+   fn resize_targets(&mut self, size: (u32, u32)) {
+      if self.target_size == size {
+         return;
+      }
+
+      for target in self.targets.drain(..) {
+         target.delete();
+      }
+      for _ in 0..2 {
+         let target = render_target(size.0, size.1);
+         target.texture.set_filter(FilterMode::Nearest);
+         self.targets.push(target);
+      }
+      self.target_size = size;
+   }
+
+   // draw "source", a render target texture, to "dest" on the screen through the enabled passes.
+   // The rest of the screen is cleared to black.
+   pub fn draw(&mut self, source: Texture2D, dest: Rect) {
+      let size = dest.size();
+      let enabled: Vec<Material> = self.passes
+         .iter()
+         .filter(|pass| pass.enabled)
+         .map(|pass| pass.material)
+         .collect();
+      if enabled.len() > 1 {
+         self.resize_targets((size.x as u32, size.y as u32));
+      }
+
+      let params = DrawTextureParams {
+         dest_size: Some(size),
+         flip_y: true,
+         ..Default::default()
+      };
+
+      let mut texture = source;
+      let mut corner = vec2(0.0, 0.0);
+      for (step, material) in enabled.iter().enumerate() {
+         // the last pass draws to the screen, the others to the targets in turn
+         let target = Some(step).filter(|step| step + 1 < enabled.len()).map(|step| self.targets[step % 2]);
+         match target {
+            Some(target) => set_camera(&Camera2D {
+               render_target: Some(target),
+               ..Camera2D::from_display_rect(Rect::new(0.0, 0.0, size.x, size.y))
+            }),
+            None => {
+               set_default_camera();
+               corner = dest.point();
+            }
+         }
+         clear_background(BLACK);
+
+         material.set_uniform("Time", get_time() as f32);
+         material.set_uniform("Resolution", (size.x, size.y));
+         gl_use_material(*material);
+         draw_texture_ex(texture, corner.x, corner.y, WHITE, params.clone());
+         gl_use_default_material();
+
+         if let Some(target) = target {
+            texture = target.texture;
+         }
+      }
+
+      if enabled.is_empty() {
+         set_default_camera();
+         clear_background(BLACK);
+         draw_texture_ex(texture, dest.x, dest.y, WHITE, params);
+      }
+   }
+}
+
+const VERTEX_SHADER: &str = "#version 100
+attribute vec3 position;
+attribute vec2 texcoord;
+attribute vec4 color0;
+
+varying lowp vec2 uv;
+varying lowp vec4 color;
+
+uniform mat4 Model;
+uniform mat4 Projection;
+
+void main() {
+    gl_Position = Projection * Model * vec4(position, 1);
+    color = color0 / 255.0;
+    uv = texcoord;
+}
+";
+
+// shared by the fragment shaders of every pass
+const FRAGMENT_SHADER_HEADER: &str = "#version 100
+precision lowp float;
+
+varying vec4 color;
+varying vec2 uv;
+
+uniform sampler2D Texture;
+// seconds since the start of the game
+uniform float Time;
+// size the game is shown at, in screen pixels
+uniform vec2 Resolution;
+
+const float PI = 3.14159265359;
+";
+
+const BLOOM_SHADER: &str = "
+const float THRESHOLD = 0.6;
+const float STRENGTH = 1.5;
+
+void main() {
+    vec3 res = texture2D(Texture, uv).rgb * color.rgb;
+
+    // bright parts of the pixels around, 3 screen pixels apart
+    vec2 texel = 3.0 / Resolution;
+    vec3 glow = vec3(0.0);
+    for (int x = -2; x <= 2; x++) {
+        for (int y = -2; y <= 2; y++) {
+            vec3 neighbour = texture2D(Texture, uv + vec2(float(x), float(y)) * texel).rgb;
+            glow += max(neighbour - THRESHOLD, 0.0);
+        }
+    }
+
+    gl_FragColor = vec4(res + glow / 25.0 * STRENGTH, 1.0);
+}
+";
+
+const COLOR_GRADING_SHADER: &str = "
+const float SATURATION = 1.15;
+const float CONTRAST = 1.08;
+const vec3 TINT = vec3(1.04, 1.0, 0.94);
+
+void main() {
+    vec3 res = texture2D(Texture, uv).rgb * color.rgb;
+
+    float luma = dot(res, vec3(0.299, 0.587, 0.114));
+    res = mix(vec3(luma), res, SATURATION);
+    res = (res - 0.5) * CONTRAST + 0.5;
+    res *= TINT;
+
+    gl_FragColor = vec4(clamp(res, 0.0, 1.0), 1.0);
+}
+";
+
+// https://www.shadertoy.com/view/XtlSD7
+const CURVATURE_SHADER: &str = "
+vec2 CRTCurveUV(vec2 uv)
+{
+    uv = uv * 2.0 - 1.0;
+    vec2 offset = abs( uv.yx ) / vec2( 12.0, 4.0 );
+    uv = uv + uv * offset * offset;
+    uv = uv * 0.5 + 0.5;
+    return uv;
+}
+
+void main() {
+    vec2 crtUV = CRTCurveUV(uv);
+
+    vec3 res = texture2D(Texture, crtUV).rgb * color.rgb;
+    if (crtUV.x < 0.0 || crtUV.x > 1.0 || crtUV.y < 0.0 || crtUV.y > 1.0)
+    {
+        res = vec3(0.0, 0.0, 0.0);
+    }
+    gl_FragColor = vec4(res, 1.0);
+}
+";
+
+const SCANLINES_SHADER: &str = "
+void main() {
+    vec3 res = texture2D(Texture, uv).rgb * color.rgb;
+
+    float scanline = clamp( 0.95 + 0.05 * cos( PI * ( uv.y + 0.008 * Time ) * Resolution.y * 0.4 ), 0.0, 1.0 );
+    float grille = 1.0 + 0.15 * clamp( 1.0 * cos( PI * uv.x * Resolution.x * 0.4 ), 0.2, 1.0 );
+    res *= scanline * grille * 1.1;
+
+    gl_FragColor = vec4(res, 1.0);
+}
+";
+
+const VIGNETTE_SHADER: &str = "
+void main() {
+    vec3 res = texture2D(Texture, uv).rgb * color.rgb;
+
+    float vignette = uv.x * uv.y * ( 1.0 - uv.x ) * ( 1.0 - uv.y );
+    vignette = clamp( pow( 50.0 * vignette, 0.2 ), 0.0, 1.0 );
+    res *= vignette;
+
+    gl_FragColor = vec4(res, 1.0);
+}
+";
@@ -0,0 +1,83 @@
+use macroquad::prelude::*;
+
+// groups of sprites drawn one after the other, whatever their depth
+#[derive(PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Debug)]
+pub enum RenderLayer {
+   Shadows,
+   // everything standing on the ground
+   Ground,
+   // flying storks
+   Air
+}
+
+// a texture ready to be drawn with draw_texture_ex
+pub struct Sprite {
+   pub texture: Texture2D,
+   // top left corner
+   pub position: Vec2,
+   pub color: Color,
+   pub params: DrawTextureParams
+}
+
+impl Sprite {
+   pub fn draw(&self) {
+      draw_texture_ex(self.texture, self.position.x, self.position.y, self.color, self.params.clone());
+   }
+}
+
+struct QueuedSprite {
+   layer: RenderLayer,
+   // y of the feet, sprites further down the screen are in front
+   depth: f32,
+   sprite: Sprite
+}
+
+// the sprites of the entities of a frame, drawn by layer and then by depth
+pub struct RenderQueue {
+   shadow: Texture2D,
+   sprites: Vec<QueuedSprite>
+}
+
+impl RenderQueue {
+   pub fn new(shadow: Texture2D) -> RenderQueue {
+      RenderQueue {
+         shadow,
+         sprites: Vec::new()
+      }
+   }
+
+   // sprites pushed with the same layer and depth are drawn in the order they are pushed
+   pub fn push(&mut self, layer: RenderLayer, depth: f32, sprite: Sprite) {
+      self.sprites.push(QueuedSprite { layer, depth, sprite });
+   }
+
+   // a shadow "width" pixels wide centred on "anchor", under every other sprite.
+   // It gets smaller the higher above the ground what casts it is.
+   pub fn push_shadow(&mut self, anchor: Vec2, width: f32, height: f32) {
+      // height at which shadows are half their size
+      const HALF_SIZE_HEIGHT: f32 = 40.0;
+      let width = width * HALF_SIZE_HEIGHT / (HALF_SIZE_HEIGHT + height.max(0.0));
+      let size = vec2(width, width * self.shadow.height() / self.shadow.width());
+      self.push(RenderLayer::Shadows, anchor.y, Sprite {
+         texture: self.shadow,
+         position: anchor - size * 0.5,
+         color: WHITE,
+         params: DrawTextureParams {
+            dest_size: Some(size),
+            ..Default::default()
+         }
+      });
+   }
+
+   // draw the queued sprites and empty the queue
+   pub fn draw(&mut self) {
+      // stable, so sprites of the same depth keep their order
+      self.sprites.sort_by(|a, b| {
+         a.layer.cmp(&b.layer).then(a.depth.partial_cmp(&b.depth).unwrap_or(std::cmp::Ordering::Equal))
+      });
+
+      for queued in self.sprites.drain(..) {
+         queued.sprite.draw();
+      }
+   }
+}
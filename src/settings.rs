@@ -0,0 +1,111 @@
+use std::fs;
+
+use crate::tilemap::fields;
+
+// how the game window opens.
+// There is no borderless mode, miniquad cannot open a window without decorations.
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum WindowMode {
+   Windowed,
+   // at the desktop resolution
+   Fullscreen
+}
+
+impl WindowMode {
+   pub const ALL: [WindowMode; 2] = [WindowMode::Windowed, WindowMode::Fullscreen];
+
+   pub fn name(self) -> &'static str {
+      match self {
+         WindowMode::Windowed => "windowed",
+         WindowMode::Fullscreen => "fullscreen",
+      }
+   }
+
+   pub fn from_name(name: &str) -> Option<WindowMode> {
+      WindowMode::ALL.iter().copied().find(|mode| mode.name() == name)
+   }
+
+   pub fn next(self) -> WindowMode {
+      let index = WindowMode::ALL.iter().position(|mode| *mode == self).unwrap_or(0);
+      WindowMode::ALL[(index + 1) % WindowMode::ALL.len()]
+   }
+}
+
+// Options of the player, kept between games in a text file:
+//
+// #Window#
+// mode, windowed|fullscreen
+// #PostProcess#
+// pass name, true|false
+pub struct Settings {
+   pub window_mode: WindowMode,
+   // post-process passes by name, with whether they are enabled
+   pub post_process: Vec<(String, bool)>
+}
+
+impl Default for Settings {
+   fn default() -> Settings {
+      Settings {
+         window_mode: WindowMode::Fullscreen,
+         post_process: Vec::new()
+      }
+   }
+}
+
+impl Settings {
+   // settings saved at "path", the defaults if there are none.
+   // Lines that cannot be understood are reported and skipped.
+   pub fn load(path: &str) -> Settings {
+      let mut settings = Settings::default();
+      let file = match fs::read_to_string(path) {
+         Ok(file) => file,
+         Err(_) => return settings
+      };
+
+      let mut section = "";
+      for (line_index, line) in file.lines().enumerate() {
+         if line.trim().starts_with('#') {
+            section = line.trim();
+            continue;
+         }
+         if line.trim().is_empty() {
+            continue;
+         }
+
+         match (section, fields(line).as_slice()) {
+            ("#Window#", [(_, "mode"), (column, mode)]) => match WindowMode::from_name(mode) {
+               Some(mode) => settings.window_mode = mode,
+               None => eprintln!("{}, line {}, column {}: expected windowed or fullscreen",
+                  path, line_index + 1, column),
+            },
+            ("#Window#", _) => eprintln!("{}, line {}: expected \"mode, windowed|fullscreen\"", path, line_index + 1),
+            ("#PostProcess#", [(_, name), (_, "true")]) => settings.set_post_process(name, true),
+            ("#PostProcess#", [(_, name), (_, "false")]) => settings.set_post_process(name, false),
+            ("#PostProcess#", _) => eprintln!("{}, line {}: expected \"pass name, true|false\"", path, line_index + 1),
+            _ => eprintln!("{}, line {}: expected #Window# or #PostProcess# before any content", path, line_index + 1),
+         }
+      }
+
+      settings
+   }
+
+   pub fn save(&self, path: &str) -> std::io::Result<()> {
+      let mut text = format!("#Window#\nmode, {}\n#PostProcess#\n", self.window_mode.name());
+      for (name, enabled) in &self.post_process {
+         text += &format!("{}, {}\n", name, enabled);
+      }
+      fs::write(path, text)
+   }
+
+   // whether the post-process pass "name" is enabled, None if it was never set
+   pub fn post_process(&self, name: &str) -> Option<bool> {
+      self.post_process.iter().find(|(pass, _)| pass == name).map(|(_, enabled)| *enabled)
+   }
+
+   pub fn set_post_process(&mut self, name: &str, enabled: bool) {
+      match self.post_process.iter_mut().find(|(pass, _)| pass == name) {
+         Some((_, pass_enabled)) => *pass_enabled = enabled,
+         None => self.post_process.push((name.to_string(), enabled)),
+      }
+   }
+}
@@ -1,6 +1,7 @@
 use macroquad::prelude::*;
 use macroquad_platformer::*;
 use crate::mover::*;
+use crate::animation::{Animation, Animator};
 
 #[derive(PartialEq)]
 pub enum StorkState {
@@ -8,16 +9,17 @@ pub enum StorkState {
    Unloaded
 }
 
+// pixels above the ground storks fly at
+pub const STORK_ALTITUDE: f32 = 40.0;
+
 pub struct Stork {
    pub state: StorkState,
+   // position on the ground, under the stork
    pub pos: Vec2,
    pub dest: Vec2,
    pub speed: Vec2,
 
-   pub frame_countdown: f32,
-   pub frame_time: f32,
-   pub anim_index: f32
-
+   pub animator: Animator
 }
 
 impl Stork {
@@ -32,37 +34,19 @@ impl Stork {
 
       if (x*x + y*y < min_distance_sq && self.state == StorkState::Loaded) {
          self.state = StorkState::Unloaded;
-         self.anim_index = 0.0;
-         self.frame_countdown = 0.0;
          unloaded = true;
       }
 
       return unloaded;
    }
-
-   pub fn update_animation(&mut self, dt: f32) {
-      self.frame_countdown += dt;
-
-      if self.frame_countdown > self.frame_time {
-         self.anim_index += 1.0;
-         self.frame_countdown = 0.0;
-
-         if self.anim_index == 3.0 {
-            self.anim_index = 0.0;
-         }
-      }
-
-
-   }
 }
 
-
 pub struct StorkFactory {
    
 }
 
 impl StorkFactory {
-   pub fn create_stork(dest: Vec2, screen_width: f32) -> Stork {
+   pub fn create_stork(dest: Vec2, screen_width: f32, animation: &Animation) -> Stork {
 
       let mut pos = Vec2::new(0.0, dest.y);
       let mut speed = Vec2::new(0.0, 0.0);
@@ -81,9 +65,7 @@ impl StorkFactory {
          dest: dest,
          speed: speed,
 
-         frame_countdown: 0.0,
-         frame_time: 0.2,
-         anim_index: 0.0
+         animator: Animator::new(animation)
       }
    }
 }
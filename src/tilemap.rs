@@ -11,7 +11,7 @@ pub struct TileData {
 }
 
 impl TileData {
-   pub fn draw(&mut self, texture: &Texture2D) {
+   pub fn draw(&self, texture: &Texture2D) {
       draw_texture_ex(*texture,
          self.pos.x,
          self.pos.y,
@@ -25,116 +25,818 @@ impl TileData {
 }
 
 
+// a named grid of tiles, empty cells are None
+pub struct TileLayer {
+   pub name: String,
+   // whether the collidable tiles of this layer block movement
+   pub collision: bool,
+   // drawn after the actors, by draw_overlay
+   pub overlay: bool,
+   pub tiles: Vec<Option<TileData>>
+}
+
 pub struct TileMap {
    pub tile_textures: Vec<Texture2D>,
-   pub map: Vec<TileData>,
+   // texture file of each tile type, as written in the map file
+   pub tile_paths: Vec<String>,
    pub collision_map: Vec<bool>,
-
-   map_size: Vec2
+   // whether each tile type is water, where thrown pandas drown
+   pub water_map: Vec<bool>,
+   // in drawing order
+   pub layers: Vec<TileLayer>,
+   // where pandas appear, in world pixels. Anywhere walkable if empty.
+   pub spawn_points: Vec<Vec2>,
+   // where bamboo grows, in world pixels. Anywhere walkable if empty.
+   pub bamboo_regions: Vec<Rect>,
+
+   map_size: Vec2,
+   tile_size: Vec2,
+
+   // the ground and overlay layers pre-rendered, redrawn only when a tile changes
+   ground_target: Option<RenderTarget>,
+   overlay_target: Option<RenderTarget>,
+   dirty: bool,
+
+   // the World layer with the colliders of the map
+   static_layer: Option<StaticLayer>,
+   // the World layer with the colliders stopping thrown pandas, AIR_TAG
+   air_layer: Option<StaticLayer>
 }
 
+// World tag of the colliders of the map stopping what flies over it: all but water
+pub const AIR_TAG: u8 = 2;
+
 impl TileMap {
-   pub fn draw(&mut self) {
-      for tile in &mut self.map {
-         tile.draw(&self.tile_textures[tile.texture_index]);
+   // draw the layers below the actors
+   pub fn draw_ground(&mut self) {
+      self.draw_layers(false);
+   }
+
+   // draw the layers above the actors
+   pub fn draw_overlay(&mut self) {
+      self.draw_layers(true);
+   }
+
+   fn draw_layers(&mut self, overlay: bool) {
+      if self.dirty {
+         self.ground_target = self.render(false, self.ground_target);
+         self.overlay_target = self.render(true, self.overlay_target);
+         self.dirty = false;
+      }
+
+      let target = if overlay { self.overlay_target } else { self.ground_target };
+      if let Some(target) = target {
+         draw_texture_ex(target.texture,
+            0.0,
+            0.0,
+            WHITE,
+            DrawTextureParams {
+               dest_size: Some(self.map_size * self.tile_size),
+               flip_y: true,
+               ..Default::default()
+         });
+      }
+   }
+
+   // render the ground or overlay layers, None if there are none
+   fn render(&self, overlay: bool, target: Option<RenderTarget>) -> Option<RenderTarget> {
+      if !self.layers.iter().any(|layer| layer.overlay == overlay) {
+         return None;
+      }
+
+      let size = self.map_size * self.tile_size;
+      let target = target.unwrap_or_else(|| {
+         let target = render_target(size.x as u32, size.y as u32);
+         target.texture.set_filter(FilterMode::Nearest);
+         target
+      });
+
+      push_camera_state();
+      set_camera(&Camera2D {
+         render_target: Some(target),
+         ..Camera2D::from_display_rect(Rect::new(0.0, 0.0, size.x, size.y))
+      });
+      clear_background(Color::new(0.0, 0.0, 0.0, 0.0));
+
+      for layer in self.layers.iter().filter(|layer| layer.overlay == overlay) {
+         for tile in layer.tiles.iter().flatten() {
+            tile.draw(&self.tile_textures[tile.texture_index]);
+         }
+      }
+
+      pop_camera_state();
+      Some(target)
+   }
+
+   pub fn layer(&self, name: &str) -> Option<&TileLayer> {
+      self.layers.iter().find(|layer| layer.name == name)
+   }
+
+   pub fn tile_size(&self) -> Vec2 {
+      self.tile_size
+   }
+
+   // area covered by the map, in world pixels
+   pub fn bounds(&self) -> Rect {
+      let size = self.map_size * self.tile_size;
+      Rect::new(0.0, 0.0, size.x, size.y)
+   }
+
+   // tile containing the world position pos, may be outside of the map
+   pub fn world_to_tile(&self, pos: Vec2) -> Vec2 {
+      (pos / self.tile_size).floor()
+   }
+
+   // world position of the top left corner of tile loc
+   pub fn tile_to_world(&self, loc: Vec2) -> Vec2 {
+      loc * self.tile_size
+   }
+
+   // index of tile loc in the layers, None outside of the map
+   fn index(&self, loc: Vec2) -> Option<usize> {
+      if loc.x < 0.0 || loc.y < 0.0 || loc.x >= self.map_size.x || loc.y >= self.map_size.y {
+         return None;
+      }
+      Some((loc.y as usize) * (self.map_size.x as usize) + loc.x as usize)
+   }
+
+   // tile at loc of layer "layer", None outside of the map or on empty cells
+   pub fn tile_at(&self, layer: &str, loc: Vec2) -> Option<&TileData> {
+      let index = self.index(loc)?;
+      self.layer(layer)?.tiles[index].as_ref()
+   }
+
+   // change the tile at loc, in tiles, of layer "layer", None to clear it.
+   // Returns false if there is no such layer or loc is outside of the map.
+   // The World colliders are not updated.
+   pub fn set_tile(&mut self, layer: &str, loc: Vec2, texture_index: Option<usize>) -> bool {
+      let index = match self.index(loc) {
+         Some(index) => index,
+         None => return false
+      };
+      let collision_map = &self.collision_map;
+      let tile_size = self.tile_size;
+
+      let layer = match self.layers.iter_mut().find(|l| l.name == layer) {
+         Some(layer) => layer,
+         None => return false
+      };
+
+      layer.tiles[index] = texture_index.map(|texture_index| TileData {
+         collider: layer.collision && collision_map[texture_index],
+         pos: loc * tile_size,
+         size: tile_size,
+         texture_index
+      });
+      self.dirty = true;
+      true
+   }
+
+   // redraw the map on next draw, needed after changing layers or tile_textures directly
+   pub fn invalidate(&mut self) {
+      self.dirty = true;
+   }
+
+   // whether tile loc blocks movement, tiles outside of the map do
+   pub fn is_collidable(&self, loc: Vec2) -> bool {
+      match self.index(loc) {
+         Some(index) => self.layers.iter().any(|layer| {
+            matches!(layer.tiles[index], Some(TileData { collider: true, .. }))
+         }),
+         None => true
       }
    }
 
-   pub fn is_collidable(&mut self, loc: Vec2) -> bool {
-      let index = (loc.y * self.map_size.x + loc.x) as usize;
+   // whether the world position pos is on a water tile of any layer, positions
+   // outside of the map are not
+   pub fn is_water(&self, pos: Vec2) -> bool {
+      match self.index(self.world_to_tile(pos)) {
+         Some(index) => self.layers.iter().any(|layer| {
+            matches!(&layer.tiles[index], Some(tile) if self.water_map[tile.texture_index])
+         }),
+         None => false
+      }
+   }
+
+   // whether all the tiles overlapping area, in world pixels, are walkable
+   pub fn is_area_walkable(&self, area: Rect) -> bool {
+      let first = self.world_to_tile(area.point());
+      // the right and bottom edges are excluded
+      let last = self.world_to_tile(area.point() + area.size() - vec2(0.001, 0.001));
+
+      for y in first.y as i32..=last.y as i32 {
+         for x in first.x as i32..=last.x as i32 {
+            if self.is_collidable(vec2(x as f32, y as f32)) {
+               return false;
+            }
+         }
+      }
+      true
+   }
+
+   // random top left corner of an area of "size" world pixels lying on walkable tiles only,
+   // None if no such place was found
+   pub fn random_walkable_point(&self, size: Vec2) -> Option<Vec2> {
+      let walkable: Vec<Vec2> = (0..self.map_size.y as i32)
+         .flat_map(|y| (0..self.map_size.x as i32).map(move |x| vec2(x as f32, y as f32)))
+         .filter(|loc| !self.is_collidable(*loc))
+         .collect();
+
+      if walkable.is_empty() {
+         return None;
+      }
+
+      const ATTEMPTS: usize = 100;
+      for _ in 0..ATTEMPTS {
+         let tile = self.tile_to_world(walkable[rand::gen_range(0, walkable.len())]);
+         let pos = vec2(
+            rand::gen_range(tile.x, tile.x + self.tile_size.x),
+            rand::gen_range(tile.y, tile.y + self.tile_size.y)).floor();
+
+         if self.is_area_walkable(Rect::new(pos.x, pos.y, size.x, size.y)) {
+            return Some(pos);
+         }
+      }
+      None
+   }
+
+   // top left corner of the walkable place for "area" closest to it, searching up to
+   // "radius" world pixels away in steps of a quarter of a tile
+   pub fn nearest_walkable_point(&self, area: Rect, radius: f32) -> Option<Vec2> {
+      if self.is_area_walkable(area) {
+         return Some(area.point());
+      }
+
+      let step = self.tile_size / 4.0;
+      let steps = (vec2(radius, radius) / step).ceil();
+      let mut offsets: Vec<Vec2> = (-steps.y as i32..=steps.y as i32)
+         .flat_map(|y| (-steps.x as i32..=steps.x as i32).map(move |x| vec2(x as f32, y as f32) * step))
+         .filter(|offset| offset.length() <= radius)
+         .collect();
+      offsets.sort_by(|a, b| a.length().partial_cmp(&b.length()).unwrap_or(std::cmp::Ordering::Equal));
+
+      offsets
+         .into_iter()
+         .map(|offset| (area.point() + offset).round())
+         .find(|pos| self.is_area_walkable(Rect::new(pos.x, pos.y, area.w, area.h)))
+   }
+
+   // random top left corner of an area of "size" world pixels in a random region,
+   // anywhere walkable if there are no regions
+   fn random_point_in(&self, regions: &[Rect], size: Vec2) -> Option<Vec2> {
+      if regions.is_empty() {
+         return self.random_walkable_point(size);
+      }
+
+      const ATTEMPTS: usize = 100;
+      for _ in 0..ATTEMPTS {
+         let region = regions[rand::gen_range(0, regions.len())];
+         let pos = vec2(
+            rand::gen_range(region.x, (region.right() - size.x).max(region.x)),
+            rand::gen_range(region.y, (region.bottom() - size.y).max(region.y))).floor();
+
+         if self.is_area_walkable(Rect::new(pos.x, pos.y, size.x, size.y)) {
+            return Some(pos);
+         }
+      }
+      None
+   }
+
+   // top left corner of a panda of "size" world pixels at a random spawn point
+   pub fn random_spawn_point(&self, size: Vec2) -> Option<Vec2> {
+      let regions: Vec<Rect> = self.spawn_points
+         .iter()
+         .map(|point| Rect::new(point.x - size.x / 2.0, point.y - size.y / 2.0, size.x, size.y))
+         .collect();
+      self.random_point_in(&regions, size)
+   }
+
+   // top left corner of a bamboo of "size" world pixels in a random bamboo region
+   pub fn random_bamboo_point(&self, size: Vec2) -> Option<Vec2> {
+      self.random_point_in(&self.bamboo_regions, size)
+   }
+
+   // update the World colliders after tiles changed
+   pub fn rebuild_colliders(&self, world: &mut World) {
+      if let Some(static_layer) = self.static_layer {
+         world.set_static_colliders(static_layer, self.static_colliders(true), self.map_size.x as usize);
+      }
+      if let Some(air_layer) = self.air_layer {
+         world.set_static_colliders(air_layer, self.static_colliders(false), self.map_size.x as usize);
+      }
+   }
+
+   // the map in the map file format read by load_tilemap
+   pub fn to_text(&self) -> String {
+      let mut text = String::from("#Tiles#\n");
+      for (index, path) in self.tile_paths.iter().enumerate() {
+         text += &format!("{}, {}, {}", path, index, self.collision_map[index]);
+         if self.water_map[index] {
+            text += ", true";
+         }
+         text += "\n";
+      }
+
+      for (index, layer) in self.layers.iter().enumerate() {
+         if index == 0 && layer.name == "ground" && layer.collision && !layer.overlay {
+            text += "#Map#\n";
+         } else {
+            let placement = if layer.overlay { "above" } else { "below" };
+            text += &format!("#Layer# {}, {}, {}\n", layer.name, layer.collision, placement);
+         }
+
+         for row in layer.tiles.chunks(self.map_size.x as usize) {
+            let row: Vec<String> = row
+               .iter()
+               .map(|tile| match tile {
+                  Some(tile) => tile.texture_index.to_string(),
+                  None => "-".to_string()
+               })
+               .collect();
+            text += &row.join(",");
+            text += "\n";
+         }
+      }
+
+      if !self.spawn_points.is_empty() {
+         text += "#Spawns#\n";
+         for point in &self.spawn_points {
+            text += &format!("{}, {}\n", point.x, point.y);
+         }
+      }
+
+      if !self.bamboo_regions.is_empty() {
+         text += "#Bamboo#\n";
+         for region in &self.bamboo_regions {
+            text += &format!("{}, {}, {}, {}\n", region.x, region.y, region.w, region.h);
+         }
+      }
 
-      return self.collision_map[self.map[index].texture_index];
+      text
    }
 
+   pub fn save(&self, path: &str) -> Result<(), TileMapError> {
+      fs::write(path, self.to_text()).map_err(|error| TileMapError::Io {
+         path: path.to_string(),
+         error: error.to_string()
+      })
+   }
+
+   // one static collider tile per cell, solid if any layer has a collider there.
+   // Water tiles are left out unless "water" is true.
+   pub fn static_colliders(&self, water: bool) -> Vec<Tile> {
+      (0..self.layers.first().map_or(0, |layer| layer.tiles.len()))
+         .map(|index| {
+            let solid = self.layers.iter().any(|layer| match &layer.tiles[index] {
+               Some(tile) => tile.collider && (water || !self.water_map[tile.texture_index]),
+               None => false
+            });
+            if solid { Tile::Solid } else { Tile::Empty }
+         })
+         .collect()
+   }
+
+}
+
+
+// a problem in a map file, lines and columns start at 1
+#[derive(Debug)]
+pub enum TileMapError {
+   Io { path: String, error: String },
+   // a non empty line before the first section
+   UnexpectedLine { line: usize },
+   // a #Layer# header without a name
+   MissingLayerName { line: usize },
+   DuplicateLayer { line: usize, name: String },
+   // the placement of a #Layer# should be "below" or "above"
+   InvalidPlacement { line: usize, column: usize, value: String },
+   // a line with less columns than its section needs
+   MissingColumn { line: usize, column: usize, name: &'static str },
+   UnexpectedColumn { line: usize, column: usize },
+   InvalidNumber { line: usize, column: usize, value: String },
+   InvalidCoordinate { line: usize, column: usize, value: String },
+   InvalidCollider { line: usize, column: usize, value: String },
+   // the index column of a #Tiles# line is not the position of the tile in the list
+   IndexMismatch { line: usize, column: usize, declared: usize, expected: usize },
+   Texture { line: usize, column: usize, path: String, error: String },
+   UnknownTile { line: usize, column: usize, index: usize, tile_count: usize },
+   // a row with another width than the first row of the map
+   RaggedRow { line: usize, width: usize, expected: usize },
+   // a layer with another number of rows than the first layer, "line" is its header
+   LayerHeight { line: usize, name: String, height: usize, expected: usize },
+   EmptyMap,
+}
+
+impl std::fmt::Display for TileMapError {
+   fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+      match self {
+         TileMapError::Io { path, error } =>
+            write!(f, "could not access {}: {}", path, error),
+         TileMapError::UnexpectedLine { line } =>
+            write!(f, "line {}: expected #Tiles#, #Map#, #Layer#, #Spawns# or #Bamboo# before any content", line),
+         TileMapError::MissingLayerName { line } =>
+            write!(f, "line {}: layers are written as \"#Layer# name, collision, below|above\"", line),
+         TileMapError::DuplicateLayer { line, name } =>
+            write!(f, "line {}: there is already a layer named \"{}\"", line, name),
+         TileMapError::InvalidPlacement { line, column, value } =>
+            write!(f, "line {}, column {}: layer placement should be below or above, not \"{}\"", line, column, value),
+         TileMapError::MissingColumn { line, column, name } =>
+            write!(f, "line {}, column {}: missing {} column", line, column, name),
+         TileMapError::UnexpectedColumn { line, column } =>
+            write!(f, "line {}, column {}: unexpected column", line, column),
+         TileMapError::InvalidNumber { line, column, value } =>
+            write!(f, "line {}, column {}: \"{}\" is not a tile index or -", line, column, value),
+         TileMapError::InvalidCoordinate { line, column, value } =>
+            write!(f, "line {}, column {}: \"{}\" is not a number of pixels", line, column, value),
+         TileMapError::InvalidCollider { line, column, value } =>
+            write!(f, "line {}, column {}: expected true or false, not \"{}\"", line, column, value),
+         TileMapError::IndexMismatch { line, column, declared, expected } =>
+            write!(f, "line {}, column {}: tile declared as index {} is tile {} of the #Tiles# list", line, column, declared, expected),
+         TileMapError::Texture { line, column, path, error } =>
+            write!(f, "line {}, column {}: could not load texture {}: {}", line, column, path, error),
+         TileMapError::UnknownTile { line, column, index, tile_count } =>
+            write!(f, "line {}, column {}: unknown tile {}, the #Tiles# section defines {} tiles", line, column, index, tile_count),
+         TileMapError::RaggedRow { line, width, expected } =>
+            write!(f, "line {}: row has {} tiles, the rows above have {}", line, width, expected),
+         TileMapError::LayerHeight { line, name, height, expected } =>
+            write!(f, "line {}: layer \"{}\" has {} rows, the first layer has {}", line, name, height, expected),
+         TileMapError::EmptyMap =>
+            write!(f, "the map has no tiles"),
+      }
+   }
 }
 
+impl std::error::Error for TileMapError {}
+
+// comma separated fields of a line, trimmed, with the column they start at
+pub fn fields(line: &str) -> Vec<(usize, &str)> {
+   let mut fields = Vec::new();
+   let mut start = 0;
+
+   for field in line.split(',') {
+      let trimmed = field.trim();
+      let offset = field.len() - field.trim_start().len();
+      fields.push((line[..start + offset].chars().count() + 1, trimmed));
+      start += field.len() + 1;
+   }
+
+   fields
+}
+
+fn parse_index(line: usize, (column, value): (usize, &str)) -> Result<usize, TileMapError> {
+   value.parse().map_err(|_| TileMapError::InvalidNumber {
+      line,
+      column,
+      value: value.to_string()
+   })
+}
+
+fn parse_coordinate(line: usize, (column, value): (usize, &str)) -> Result<f32, TileMapError> {
+   value.parse().map_err(|_| TileMapError::InvalidCoordinate {
+      line,
+      column,
+      value: value.to_string()
+   })
+}
+
+// the non empty field "index" of a line, "name" describes it in errors
+fn required_field<'a>(
+   line: &str,
+   line_number: usize,
+   fields: &[(usize, &'a str)],
+   index: usize,
+   name: &'static str) -> Result<(usize, &'a str), TileMapError>
+{
+   fields.get(index).copied().filter(|(_, value)| !value.is_empty()).ok_or(
+      TileMapError::MissingColumn { line: line_number, column: line.chars().count() + 1, name })
+}
+
+fn check_field_count(line_number: usize, fields: &[(usize, &str)], count: usize) -> Result<(), TileMapError> {
+   match fields.get(count) {
+      Some((column, _)) => Err(TileMapError::UnexpectedColumn { line: line_number, column: *column }),
+      None => Ok(())
+   }
+}
+
+fn parse_bool(line: usize, (column, value): (usize, &str)) -> Result<bool, TileMapError> {
+   match value {
+      "true" => Ok(true),
+      "false" => Ok(false),
+      _ => Err(TileMapError::InvalidCollider {
+         line,
+         column,
+         value: value.to_string()
+      }),
+   }
+}
+
+// part of the map file being read
+#[derive(PartialEq)]
+enum Section {
+   None,
+   Tiles,
+   // #Map# or #Layer#
+   Layer,
+   Spawns,
+   Bamboo
+}
+
+// check the number of rows of the last layer read against the first layer
+fn check_layer_height(
+   layers: &[TileLayer],
+   header_lines: &[usize],
+   width: usize,
+   height: usize) -> Result<(), TileMapError>
+{
+   if let Some(last) = layers.last().filter(|_| layers.len() > 1) {
+      let last_height = last.tiles.len() / width.max(1);
+      if last_height != height {
+         return Err(TileMapError::LayerHeight {
+            line: header_lines[layers.len() - 1],
+            name: last.name.clone(),
+            height: last_height,
+            expected: height
+         });
+      }
+   }
+   Ok(())
+}
+
+// Map files list the tile types, then the layers from bottom to top:
+//
+// #Tiles#
+// texture path, index, collider[, water]
+// #Map#
+// ground tile indices, comma separated
+// #Layer# name, collision, below|above
+// tile indices, - for empty cells
+// #Spawns#
+// x, y
+// #Bamboo#
+// x, y, width, height
+//
+// "#Map#" is the colliding "ground" layer drawn below the actors.
+// Spawn points and bamboo regions are optional, in world pixels.
+pub async fn load_tilemap(path: &str, world: &mut World) -> Result<TileMap, TileMapError> {
+   let file = fs::read_to_string(path).map_err(|error| TileMapError::Io {
+      path: path.to_string(),
+      error: error.to_string()
+   })?;
+
+   parse_tilemap(&file, world).await
+}
+
+// replace "tilemap" with the map file at "path", updating its World colliders.
+// On error the current map is kept.
+pub async fn reload_tilemap(path: &str, tilemap: &mut TileMap, world: &mut World) -> Result<(), TileMapError> {
+   let mut reloaded = load_tilemap(path, &mut World::new()).await?;
+   reloaded.static_layer = tilemap.static_layer;
+   reloaded.air_layer = tilemap.air_layer;
+
+   for texture in &tilemap.tile_textures {
+      texture.delete();
+   }
+   for target in tilemap.ground_target.iter().chain(&tilemap.overlay_target) {
+      target.delete();
+   }
+
+   *tilemap = reloaded;
+   tilemap.rebuild_colliders(world);
+   Ok(())
+}
 
-pub async fn load_tilemap(path: &str, world: &mut World) -> TileMap {
+// build a map from the content of a map file, see load_tilemap
+pub async fn parse_tilemap(file: &str, world: &mut World) -> Result<TileMap, TileMapError> {
    let mut tilemap = TileMap { 
       tile_textures: Vec::new(),
-      map: Vec::new(),
+      tile_paths: Vec::new(),
       collision_map: Vec::new(),
-      map_size: Vec2::new(0.0, 0.0) };
+      water_map: Vec::new(),
+      layers: Vec::new(),
+      spawn_points: Vec::new(),
+      bamboo_regions: Vec::new(),
+      map_size: Vec2::new(0.0, 0.0),
+      tile_size: Vec2::new(32.0, 32.0),
+      ground_target: None,
+      overlay_target: None,
+      dirty: true,
+      static_layer: None,
+      air_layer: None };
 
-   let file = fs::read_to_string(path).expect("Something went wrong reading the file");
    let lines = file.lines();
 
-   let mut read_tile = false;
-   let mut read_map = false;
+   let mut section = Section::None;
 
    let mut loc = Vec2::new(0.0, 0.0);
-   let size = Vec2::new(32.0, 32.0);
+   let size = tilemap.tile_size;
 
+   // set by the first row of the first layer
    let mut width = 0;
+   // rows of the first layer
    let mut height = 0;
+   let mut header_lines = Vec::new();
+
+   for (line_index, line) in lines.enumerate() {
+      let line_number = line_index + 1;
 
-   for line in lines {
       if line.contains("#Tiles#") {
-         read_tile = true;
-         read_map = false;
+         section = Section::Tiles;
+         continue;
+      } else if line.contains("#Spawns#") {
+         section = Section::Spawns;
          continue;
-      } else if line.contains("#Map#") {
-         read_tile = false;
-         read_map = true;
+      } else if line.contains("#Bamboo#") {
+         section = Section::Bamboo;
+         continue;
+      } else if line.contains("#Map#") || line.contains("#Layer#") {
+         check_layer_height(&tilemap.layers, &header_lines, width, height)?;
+
+         let layer = if line.contains("#Map#") {
+            TileLayer {
+               name: "ground".to_string(),
+               collision: true,
+               overlay: false,
+               tiles: Vec::new()
+            }
+         } else {
+            let header_start = line.find("#Layer#").unwrap() + "#Layer#".len();
+            let columns = line[..header_start].chars().count();
+            let header: Vec<(usize, &str)> = fields(&line[header_start..])
+               .into_iter()
+               .map(|(column, value)| (column + columns, value))
+               .collect();
+
+            let name = header.first()
+               .map(|(_, name)| *name)
+               .filter(|name| !name.is_empty())
+               .ok_or(TileMapError::MissingLayerName { line: line_number })?;
+            let collision = match header.get(1) {
+               Some(field) => parse_bool(line_number, *field)?,
+               None => false
+            };
+            let overlay = match header.get(2) {
+               Some((_, "below")) | None => false,
+               Some((_, "above")) => true,
+               Some((column, value)) => return Err(TileMapError::InvalidPlacement {
+                  line: line_number,
+                  column: *column,
+                  value: value.to_string()
+               }),
+            };
+            check_field_count(line_number, &header, 3)?;
+
+            TileLayer {
+               name: name.to_string(),
+               collision,
+               overlay,
+               tiles: Vec::new()
+            }
+         };
+
+         if tilemap.layer(&layer.name).is_some() {
+            return Err(TileMapError::DuplicateLayer { line: line_number, name: layer.name });
+         }
+
+         tilemap.layers.push(layer);
+         header_lines.push(line_number);
+         loc.y = 0.0;
+         section = Section::Layer;
          continue;
       }
 
-      if read_map {
-         width = 0;
-         loc.x = 0.0;
+      if line.trim().is_empty() {
+         continue;
+      }
 
-         let tile_strings: Vec<&str> = line.split(",").collect();
+      if section == Section::Layer {
+         let first_layer = tilemap.layers.len() == 1;
+         let layer = tilemap.layers.last_mut().unwrap();
+         let mut row_width = 0;
+         loc.x = 0.0;
 
-         for string in tile_strings {
-            let index = string.trim().parse().unwrap();
+         for field in fields(line) {
+            row_width += 1;
+            if field.1 == "-" {
+               layer.tiles.push(None);
+               loc.x += size.x;
+               continue;
+            }
+
+            let index = parse_index(line_number, field)?;
+
+            if index >= tilemap.collision_map.len() {
+               return Err(TileMapError::UnknownTile {
+                  line: line_number,
+                  column: field.0,
+                  index,
+                  tile_count: tilemap.collision_map.len()
+               });
+            }
 
             let t = TileData { 
-               collider: tilemap.collision_map[index],
+               collider: layer.collision && tilemap.collision_map[index],
                pos: loc,
-               size: size,
+               size,
                texture_index: index
             };
 
-            tilemap.map.push(t);
+            layer.tiles.push(Some(t));
             loc.x += size.x;
-            width += 1;
          }
-         
+
+         if (!first_layer || height > 0) && row_width != width {
+            return Err(TileMapError::RaggedRow {
+               line: line_number,
+               width: row_width,
+               expected: width
+            });
+         }
+
+         width = row_width;
          loc.y += size.y;
-         height += 1;
+         if first_layer {
+            height += 1;
+         }
 
-      } else if read_tile {
-         let tile_strings: Vec<&str> = line.split(",").collect();
-         let texture = load_texture(tile_strings[0]).await.unwrap();
+      } else if section == Section::Tiles {
+         let tile_fields = fields(line);
+         let field = |index: usize, name: &'static str| {
+            required_field(line, line_number, &tile_fields, index, name)
+         };
+
+         let (texture_column, texture_path) = field(0, "texture")?;
+         let index_field = field(1, "index")?;
+         let collider = parse_bool(line_number, field(2, "collider")?)?;
+         let water = match tile_fields.get(3) {
+            Some(water_field) => parse_bool(line_number, *water_field)?,
+            None => false
+         };
+         check_field_count(line_number, &tile_fields, 4)?;
+
+         let declared = parse_index(line_number, index_field)?;
+         if declared != tilemap.tile_textures.len() {
+            return Err(TileMapError::IndexMismatch {
+               line: line_number,
+               column: index_field.0,
+               declared,
+               expected: tilemap.tile_textures.len()
+            });
+         }
+
+         let texture = load_texture(texture_path).await.map_err(|error| TileMapError::Texture {
+            line: line_number,
+            column: texture_column,
+            path: texture_path.to_string(),
+            error: error.to_string()
+         })?;
          texture.set_filter(FilterMode::Nearest);
          tilemap.tile_textures.push(texture);
+         tilemap.tile_paths.push(texture_path.to_string());
+         tilemap.collision_map.push(collider);
+         tilemap.water_map.push(water);
 
-         if tile_strings[2].contains("true") {
-            tilemap.collision_map.push(true);
+      } else if section == Section::Spawns || section == Section::Bamboo {
+         let names: &[&'static str] = if section == Section::Spawns {
+            &["x", "y"]
          } else {
-            tilemap.collision_map.push(false);
+            &["x", "y", "width", "height"]
+         };
+
+         let line_fields = fields(line);
+         let mut values = Vec::new();
+         for (index, name) in names.iter().enumerate() {
+            let field = required_field(line, line_number, &line_fields, index, name)?;
+            values.push(parse_coordinate(line_number, field)?);
          }
+         check_field_count(line_number, &line_fields, names.len())?;
+
+         if section == Section::Spawns {
+            tilemap.spawn_points.push(vec2(values[0], values[1]));
+         } else {
+            tilemap.bamboo_regions.push(Rect::new(values[0], values[1], values[2], values[3]));
+         }
+
+      } else {
+         return Err(TileMapError::UnexpectedLine { line: line_number });
       }
 
    }
 
+   check_layer_height(&tilemap.layers, &header_lines, width, height)?;
+   if width == 0 {
+      return Err(TileMapError::EmptyMap);
+   }
+
    tilemap.map_size.x = width as f32;
    tilemap.map_size.y = height as f32;
 
-   let mut static_colliders = vec![];
-
-   for tile_data in &tilemap.map {
-      if tile_data.collider {
-         static_colliders.push(Tile::Solid);
-      } else {
-         static_colliders.push(Tile::Empty);
-      }
-   }
-
-   world.add_static_tiled_layer(static_colliders, size.x, size.y, width, 1);
+   tilemap.static_layer = Some(
+      world.add_static_tiled_layer(tilemap.static_colliders(true), size.x, size.y, width, 1));
+   tilemap.air_layer = Some(
+      world.add_static_tiled_layer(tilemap.static_colliders(false), size.x, size.y, width, AIR_TAG));
    println!("{} and {}", width, height);
 
-   return tilemap;
+   Ok(tilemap)
 }
 
 
@@ -179,3 +881,52 @@ impl PandaFactory {
 }
 */
 
+
+#[cfg(test)]
+mod tests {
+   use super::*;
+   use std::future::Future;
+   use std::task::{Context, Poll, Waker};
+
+   // parse a map without #Tiles# lines: no texture is loaded, so it never waits
+   fn parse(file: &str) -> Result<TileMap, TileMapError> {
+      let mut world = World::new();
+      let mut future = Box::pin(parse_tilemap(file, &mut world));
+      match future.as_mut().poll(&mut Context::from_waker(Waker::noop())) {
+         Poll::Ready(result) => result,
+         Poll::Pending => panic!("parse_tilemap waited for a texture"),
+      }
+   }
+
+   #[test]
+   fn ragged_row() {
+      assert!(matches!(
+         parse("#Map#\n-, -\n-, -, -"),
+         Err(TileMapError::RaggedRow { line: 3, width: 3, expected: 2 })));
+      // the first row of a later layer is checked against the first layer
+      assert!(matches!(
+         parse("#Map#\n-, -\n\n#Layer# rocks\n-"),
+         Err(TileMapError::RaggedRow { line: 5, width: 1, expected: 2 })));
+   }
+
+   #[test]
+   fn index_mismatch() {
+      assert!(matches!(
+         parse("#Tiles#\nassets/grass.png, 1, false"),
+         Err(TileMapError::IndexMismatch { line: 2, column: 19, declared: 1, expected: 0 })));
+   }
+
+   #[test]
+   fn unknown_tile() {
+      assert!(matches!(
+         parse("#Map#\n-,  -\n-, 3"),
+         Err(TileMapError::UnknownTile { line: 3, column: 4, index: 3, tile_count: 0 })));
+   }
+
+   #[test]
+   fn empty_cells_only() {
+      let tilemap = parse("#Map#\n-, -\n-, -\n#Spawns#\n16, 16").unwrap();
+      assert_eq!(tilemap.spawn_points, [vec2(16.0, 16.0)]);
+      assert!(matches!(parse("#Map#\n"), Err(TileMapError::EmptyMap)));
+   }
+}
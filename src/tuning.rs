@@ -0,0 +1,120 @@
+use std::fs;
+
+use crate::tilemap::fields;
+
+// Gameplay values, read from a text file with one value per line:
+//
+// name, value
+//
+// The file is watched while the game runs, changes apply at once.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Tuning {
+   // in world pixels per second
+   pub player_speed: f32,
+   // seconds after a throw before the player can grab again
+   pub throw_cooldown: f32,
+   // pandas closer than this fall in love, in world pixels
+   pub love_range: f32,
+   // seconds after falling in love before a panda can again
+   pub love_cooldown: f32,
+   // seconds a panda lives
+   pub panda_lifetime: f32,
+   // pandas closer than this to the player can be grabbed, in world pixels
+   pub grab_range: f32,
+   pub start_bamboo: f32,
+   // bamboo eaten by each panda per second
+   pub hunger_rate: f32,
+   // seconds between two bamboo deliveries
+   pub bamboo_interval: f32,
+   pub bamboo_delivery: f32
+}
+
+impl Default for Tuning {
+   fn default() -> Tuning {
+      Tuning {
+         player_speed: 100.0,
+         throw_cooldown: 2.0,
+         love_range: 32.0,
+         love_cooldown: 3.0,
+         panda_lifetime: 20.0,
+         grab_range: 20.0,
+         start_bamboo: 100.0,
+         hunger_rate: 0.25,
+         bamboo_interval: 10.0,
+         bamboo_delivery: 10.0
+      }
+   }
+}
+
+impl Tuning {
+   // values by their name in the file
+   fn values(&mut self) -> [(&'static str, &mut f32); 10] {
+      [
+         ("player_speed", &mut self.player_speed),
+         ("throw_cooldown", &mut self.throw_cooldown),
+         ("love_range", &mut self.love_range),
+         ("love_cooldown", &mut self.love_cooldown),
+         ("panda_lifetime", &mut self.panda_lifetime),
+         ("grab_range", &mut self.grab_range),
+         ("start_bamboo", &mut self.start_bamboo),
+         ("hunger_rate", &mut self.hunger_rate),
+         ("bamboo_interval", &mut self.bamboo_interval),
+         ("bamboo_delivery", &mut self.bamboo_delivery),
+      ]
+   }
+
+   // tuning read from "path", the defaults for the values it does not give.
+   // Lines that cannot be understood are reported and skipped.
+   pub fn load(path: &str) -> Tuning {
+      match fs::read_to_string(path) {
+         Ok(file) => Tuning::parse(path, &file),
+         Err(error) => {
+            eprintln!("could not read {}: {}", path, error);
+            Tuning::default()
+         }
+      }
+   }
+
+   fn parse(path: &str, file: &str) -> Tuning {
+      let mut tuning = Tuning::default();
+
+      for (line_index, line) in file.lines().enumerate() {
+         if line.trim().is_empty() {
+            continue;
+         }
+
+         match fields(line).as_slice() {
+            [(column, name), (value_column, value)] => {
+               let mut values = tuning.values();
+               match values.iter_mut().find(|(known, _)| known == name) {
+                  Some((_, field)) => match value.parse::<f32>() {
+                     Ok(value) if value >= 0.0 => **field = value,
+                     _ => eprintln!("{}, line {}, column {}: \"{}\" is not a positive number",
+                        path, line_index + 1, value_column, value),
+                  },
+                  None => eprintln!("{}, line {}, column {}: unknown value \"{}\"", path, line_index + 1, column, name),
+               }
+            }
+            _ => eprintln!("{}, line {}: expected \"name, value\"", path, line_index + 1),
+         }
+      }
+
+      tuning
+   }
+}
+
+#[cfg(test)]
+mod tests {
+   use super::*;
+
+   #[test]
+   fn parse() {
+      let tuning = Tuning::parse("tuning.txt", "player_speed, 80\n\nlove_range, -1\nspeed, 3\nhunger_rate, 0.5");
+
+      assert_eq!(tuning.player_speed, 80.0);
+      assert_eq!(tuning.hunger_rate, 0.5);
+      // invalid lines keep the defaults
+      assert_eq!(tuning.love_range, Tuning::default().love_range);
+      assert_eq!(Tuning { player_speed: 100.0, hunger_rate: 0.25, ..tuning }, Tuning::default());
+   }
+}
@@ -0,0 +1,169 @@
+use macroquad::prelude::*;
+use macroquad::window::get_internal_gl;
+
+use std::ops::Range;
+
+/// Quads drawn by a single geometry call, keeps the draw calls under
+/// the macroquad vertex and index limits
+const MAX_QUADS: usize = 512;
+
+/// Position, uv and color of a vertex, the form macroquad converts vertices to
+type TintedVertex = ([f32; 3], [f32; 2], [f32; 4]);
+
+/// A tile animated quad, with the sprite it currently shows
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct AnimatedQuad {
+    pub quad: usize,
+    pub x: u32,
+    pub y: u32,
+    pub sprite: u32,
+}
+
+/// Vertices of all the tiles of a tile layer, in layer pixels and white.
+/// Built once and drawn with a model matrix and a color, so drawing a layer does not
+/// look up tilesets, sprites or animations for each tile every frame.
+#[derive(Debug, Default)]
+pub(crate) struct LayerBatch {
+    /// 4 vertices for each non empty tile, row by row
+    pub vertices: Vec<Vertex>,
+    /// Layer column of each quad
+    pub columns: Vec<u32>,
+    /// First quad of each row, with one more entry for the end of the last row
+    pub rows: Vec<usize>,
+    /// First quad and texture of each run of quads sharing a texture
+    pub textures: Vec<(usize, Texture2D)>,
+    pub animated: Vec<AnimatedQuad>,
+    /// Whether the rows go from right to left
+    pub descending: bool,
+    indices: Vec<u16>,
+    /// Vertices of the quads being drawn with a color other than white
+    tinted: Vec<TintedVertex>,
+}
+
+impl LayerBatch {
+    pub fn new(descending: bool) -> LayerBatch {
+        LayerBatch {
+            rows: vec![0],
+            descending,
+            indices: (0..MAX_QUADS as u16)
+                .flat_map(|quad| {
+                    let i = quad * 4;
+                    [i, i + 1, i + 2, i, i + 2, i + 3]
+                })
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    pub fn quads(&self) -> usize {
+        self.columns.len()
+    }
+
+    /// Append a quad at the end of the current row
+    pub fn push(&mut self, column: u32, dest: Rect, texture: Texture2D, source: Rect) {
+        let quad = self.quads();
+        if self.textures.last().map(|(_, last)| *last) != Some(texture) {
+            self.textures.push((quad, texture));
+        }
+        self.columns.push(column);
+        self.vertices
+            .extend_from_slice(&quad_vertices(dest, texture, source));
+    }
+
+    /// Replace the vertices of a quad, returns false if it does not use "texture"
+    pub fn replace(&mut self, quad: usize, dest: Rect, texture: Texture2D, source: Rect) -> bool {
+        let run = self.textures.partition_point(|(start, _)| *start <= quad) - 1;
+        if self.textures[run].1 != texture {
+            return false;
+        }
+        self.vertices[quad * 4..quad * 4 + 4]
+            .copy_from_slice(&quad_vertices(dest, texture, source));
+        true
+    }
+
+    pub fn end_row(&mut self) {
+        self.rows.push(self.quads());
+    }
+
+    /// Draw the tiles of columns x0..x1 of batch rows "rows", transformed by "model"
+    /// and multiplied by "color"
+    pub fn draw(&mut self, rows: Range<usize>, x0: u32, x1: u32, model: Mat4, color: Color) {
+        let gl = unsafe { get_internal_gl() }.quad_gl;
+        gl.push_model_matrix(model);
+        gl.draw_mode(DrawMode::Triangles);
+
+        for y in rows.start..rows.end.min(self.rows.len() - 1) {
+            let (start, end) = (self.rows[y], self.rows[y + 1]);
+            let row = &self.columns[start..end];
+            let (first, last) = if self.descending {
+                (
+                    start + row.partition_point(|x| *x >= x1),
+                    start + row.partition_point(|x| *x >= x0),
+                )
+            } else {
+                (
+                    start + row.partition_point(|x| *x < x0),
+                    start + row.partition_point(|x| *x < x1),
+                )
+            };
+
+            let mut quad = first;
+            while quad < last {
+                let run = self.textures.partition_point(|(start, _)| *start <= quad) - 1;
+                let run_end = self
+                    .textures
+                    .get(run + 1)
+                    .map_or(last, |(start, _)| (*start).min(last));
+                let end = run_end.min(quad + MAX_QUADS);
+
+                let vertices = &self.vertices[quad * 4..end * 4];
+                let indices = &self.indices[..(end - quad) * 6];
+                gl.texture(Some(self.textures[run].1));
+                if color == WHITE {
+                    gl.geometry(vertices, indices);
+                } else {
+                    let color = [color.r, color.g, color.b, color.a];
+                    self.tinted.clear();
+                    self.tinted.extend(vertices.iter().map(|vertex| {
+                        let (position, uv, _): TintedVertex = (*vertex).into();
+                        (position, uv, color)
+                    }));
+                    gl.geometry(&self.tinted, indices);
+                }
+                quad = end;
+            }
+        }
+
+        gl.pop_model_matrix();
+    }
+}
+
+/// Rect of the world seen through the active camera
+pub(crate) fn camera_view() -> Rect {
+    let projection = unsafe { get_internal_gl() }.quad_gl.get_projection_matrix();
+    let inverse = projection.inverse();
+    let a = inverse.transform_point3(vec3(-1., -1., 0.));
+    let b = inverse.transform_point3(vec3(1., 1., 0.));
+    Rect::new(
+        a.x.min(b.x),
+        a.y.min(b.y),
+        (a.x - b.x).abs(),
+        (a.y - b.y).abs(),
+    )
+}
+
+fn quad_vertices(dest: Rect, texture: Texture2D, source: Rect) -> [Vertex; 4] {
+    let size = vec2(texture.width(), texture.height());
+    let (u0, v0) = (source.x / size.x, source.y / size.y);
+    let (u1, v1) = (
+        (source.x + source.w) / size.x,
+        (source.y + source.h) / size.y,
+    );
+
+    [
+        Vertex::new(dest.x, dest.y, 0., u0, v0, WHITE),
+        Vertex::new(dest.x + dest.w, dest.y, 0., u1, v0, WHITE),
+        Vertex::new(dest.x + dest.w, dest.y + dest.h, 0., u1, v1, WHITE),
+        Vertex::new(dest.x, dest.y + dest.h, 0., u0, v1, WHITE),
+    ]
+}
@@ -11,6 +11,55 @@ pub enum Error {
     TextureNotFound {
         texture: String,
     },
+    TemplateNotFound {
+        template: String,
+    },
+    ExternalTilesetNotFound {
+        tileset: String,
+    },
+    TilesetNotFound {
+        tileset: String,
+        available: Vec<String>,
+    },
+    LayerNotFound {
+        layer: String,
+    },
+    /// Tile "index" was requested from a layer with only "len" tiles
+    TileIndexOutOfRange {
+        layer: String,
+        index: usize,
+        len: usize,
+    },
+    /// Tile layer "data" has "len" tiles instead of the "expected" width * height
+    LayerDataLength {
+        layer: String,
+        expected: usize,
+        len: usize,
+    },
+    /// "sprite" is beyond the tiles of the tileset
+    SpriteOutOfRange {
+        tileset: String,
+        sprite: u32,
+        tilecount: u32,
+    },
+    /// Layer data references a gid not covered by any tileset
+    InvalidTileGid {
+        layer: String,
+        gid: u32,
+    },
+    /// Map of a world file was not provided to load_world
+    MapNotFound {
+        map: String,
+    },
+    /// World files placing maps by file name patterns are not supported
+    UnsupportedWorldPatterns,
+    WangSetNotFound {
+        wang_set: String,
+    },
+    WangColorNotFound {
+        wang_set: String,
+        color: String,
+    },
 }
 
 impl From<nanoserde::DeJsonErr> for Error {
@@ -26,11 +75,49 @@ impl From<nanoserde::DeJsonErr> for Error {
 impl std::fmt::Display for Error {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            Error::DeJsonErr { .. } | Error::TextureNotFound {..} => std::fmt::Debug::fmt(self, f),
+            Error::DeJsonErr { .. } | Error::TextureNotFound {..} | Error::TemplateNotFound {..} => std::fmt::Debug::fmt(self, f),
             Error::NonUniqueLayerName { layer } => write!(
                 f,
                 "Layer name should be unique to load tiled level in macroquad, non-unique layer name: {}", layer
             ),
+            Error::ExternalTilesetNotFound { tileset } => write!(
+                f,
+                "External tileset {} is used by the map but was not provided to load_map", tileset
+            ),
+            Error::TilesetNotFound { tileset, available } => write!(
+                f,
+                "No such tileset: {}, tilesets available: {:?}", tileset, available
+            ),
+            Error::LayerNotFound { layer } => write!(f, "No such layer: {}", layer),
+            Error::TileIndexOutOfRange { layer, index, len } => write!(
+                f,
+                "Tile index {} is out of range for layer {} with {} tiles", index, layer, len
+            ),
+            Error::LayerDataLength { layer, expected, len } => write!(
+                f,
+                "Layer {} has {} tiles of data, {} expected from its width and height", layer, len, expected
+            ),
+            Error::SpriteOutOfRange { tileset, sprite, tilecount } => write!(
+                f,
+                "Sprite {} is out of range for tileset {} with {} tiles", sprite, tileset, tilecount
+            ),
+            Error::InvalidTileGid { layer, gid } => write!(
+                f,
+                "Layer {} uses tile gid {} which does not belong to any tileset", layer, gid
+            ),
+            Error::MapNotFound { map } => write!(
+                f,
+                "Map {} is used by the world but was not provided to load_world", map
+            ),
+            Error::WangSetNotFound { wang_set } => write!(f, "No such wang set: {}", wang_set),
+            Error::WangColorNotFound { wang_set, color } => write!(
+                f,
+                "No such color in wang set {}: {}", wang_set, color
+            ),
+            Error::UnsupportedWorldPatterns => write!(
+                f,
+                "World files with \"patterns\" are not supported, list the maps in \"maps\" instead"
+            ),
         }
     }
 }
@@ -0,0 +1,48 @@
+use macroquad::prelude::*;
+
+use crate::{Object, Properties, Tile};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LayerType {
+    Tiles,
+    Objects,
+    Image,
+    Group,
+}
+
+#[derive(Debug)]
+pub struct ImageLayer {
+    pub texture: Texture2D,
+    /// Whether the image is repeated along the X axis
+    pub repeat_x: bool,
+    /// Whether the image is repeated along the Y axis
+    pub repeat_y: bool,
+}
+
+#[derive(Debug)]
+pub struct Layer {
+    pub ty: LayerType,
+    pub objects: Vec<Object>,
+    pub width: u32,
+    pub height: u32,
+    pub data: Vec<Option<Tile>>,
+    pub properties: Properties,
+
+    pub visible: bool,
+    /// Value between 0 and 1
+    pub opacity: f32,
+    /// Offset in pixels
+    pub offset: Vec2,
+    /// Parallax scrolling factor, 1 scrolls with the map
+    pub parallax: Vec2,
+    /// Multiplied with the color of everything drawn by the layer
+    pub tint: Color,
+
+    /// Only for image layers
+    pub image: Option<ImageLayer>,
+
+    /// Group this layer belongs to, None for the top level layers
+    pub parent: Option<String>,
+    /// Layers of a group, in drawing order
+    pub children: Vec<String>,
+}
@@ -2,35 +2,32 @@ use nanoserde::DeJson;
 
 use macroquad::prelude::*;
 
+use std::cell::RefCell;
 use std::collections::HashMap;
 
+mod batch;
 mod error;
+mod layer;
+mod object;
+mod orientation;
+mod properties;
 mod tiled;
+mod wang;
+mod world;
+mod writer;
 
 pub use error::Error;
-pub use tiled::layer::Property;
+pub use layer::{ImageLayer, Layer, LayerType};
+pub use object::{Object, ObjectShape, Text};
+pub use orientation::{Orientation, RenderOrder, StaggerAxis, StaggerIndex};
+pub use properties::{Properties, PropertyValue};
+pub use tiled::Property;
+pub use wang::{WangColor, WangSet, WangSetType};
+pub use world::{load_world, World, WorldMap};
 
-#[derive(Debug, Clone)]
-pub struct Object {
-    /// If not null - the object is (probably) a tile
-    pub gid: Option<u32>,
-
-    pub world_x: f32,
-    pub world_y: f32,
-    pub world_w: f32,
-    pub world_h: f32,
-
-    pub tile_x: u32,
-    pub tile_y: u32,
-    pub tile_w: u32,
-    pub tile_h: u32,
-
-    pub name: String,
-
-    pub properties: HashMap<String, String>,
-}
+use batch::{camera_view, AnimatedQuad, LayerBatch};
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Tile {
     /// id in the tileset
     pub id: u32,
@@ -38,80 +35,675 @@ pub struct Tile {
     pub tileset: String,
     /// "type" from tiled
     pub attrs: String,
+    /// Custom properties of the tile in its tileset
+    pub properties: Properties,
 }
 
-#[derive(Debug)]
-pub struct Layer {
-    pub objects: Vec<Object>,
-    pub width: u32,
-    pub height: u32,
-    pub data: Vec<Option<Tile>>,
+/// One frame of a tile animation
+#[derive(Debug, Clone, Copy)]
+pub struct AnimationFrame {
+    /// id in the tileset of the tile displayed during this frame
+    pub tile_id: u32,
+    /// Frame duration in seconds
+    pub duration: f32,
 }
 
 #[derive(Debug)]
 pub struct TileSet {
+    /// Texture of the whole tileset, empty for image collection tilesets
     pub texture: Texture2D,
+    /// Image collection tilesets have a separate texture for each tile
+    pub tile_images: HashMap<u32, Texture2D>,
 
+    /// GID of the first tile of the tileset in the map
+    pub firstgid: u32,
     pub tilewidth: i32,
     pub tileheight: i32,
     pub columns: u32,
     pub spacing: i32,
     pub margin: i32,
+    /// The number of tiles in this tileset
+    pub tilecount: u32,
+    /// Offset in pixels applied when drawing tiles of this tileset
+    pub tileoffset: Vec2,
+    /// Pixels cut from each side of the sprites when drawing.
+    /// Prevents neighbour sprites from bleeding in with linear filtering or
+    /// non-integer scales, 0 by default.
+    pub inset: f32,
+
+    /// Animated tiles of the tileset, tile id -> animation frames
+    pub animations: HashMap<u32, Vec<AnimationFrame>>,
+    /// Tiles with a type or custom properties, by id
+    pub tiles: HashMap<u32, Tile>,
+    /// Terrains of the tileset, for autotiling with Map::set_terrain
+    pub wang_sets: Vec<WangSet>,
+
+    pub properties: Properties,
 }
 
 impl TileSet {
     fn sprite_rect(&self, ix: u32) -> Rect {
         let sw = self.tilewidth as f32;
         let sh = self.tileheight as f32;
-        let sx = (ix % self.columns) as f32 * (sw + self.spacing as f32) + self.margin as f32;
-        let sy = (ix / self.columns) as f32 * (sh + self.spacing as f32) + self.margin as f32;
+        let columns = self.columns.max(1);
+        let sx = (ix % columns) as f32 * (sw + self.spacing as f32) + self.margin as f32;
+        let sy = (ix / columns) as f32 * (sh + self.spacing as f32) + self.margin as f32;
+
+        Rect::new(
+            sx + self.inset,
+            sy + self.inset,
+            sw - 2. * self.inset,
+            sh - 2. * self.inset,
+        )
+    }
+
+    /// Texture and source rect of the sprite, None if the tileset has no such sprite
+    fn sprite(&self, ix: u32) -> Option<(Texture2D, Rect)> {
+        if !self.tile_images.is_empty() {
+            return self.tile_images.get(&ix).map(|texture| {
+                let rect = Rect::new(
+                    self.inset,
+                    self.inset,
+                    texture.width() - 2. * self.inset,
+                    texture.height() - 2. * self.inset,
+                );
+                (*texture, rect)
+            });
+        }
+
+        if ix >= self.tilecount {
+            return None;
+        }
+        Some((self.texture, self.sprite_rect(ix)))
+    }
+
+    /// Size of the sprite in pixels
+    pub fn sprite_size(&self, ix: u32) -> Vec2 {
+        match self.tile_images.get(&ix) {
+            Some(texture) => vec2(texture.width(), texture.height()),
+            None => vec2(self.tilewidth as f32, self.tileheight as f32),
+        }
+    }
+
+    /// Tile id to display for "ix" after "time" seconds of animation.
+    /// Tiles without animation are returned as is.
+    fn animated_sprite(&self, ix: u32, time: f64) -> u32 {
+        let frames = match self.animations.get(&ix) {
+            Some(frames) if !frames.is_empty() => frames,
+            _ => return ix,
+        };
+
+        let total: f32 = frames.iter().map(|frame| frame.duration).sum();
+        if total <= 0. {
+            return frames[0].tile_id;
+        }
 
-        // TODO: configure tiles margin
-        Rect::new(sx + 1.1, sy + 1.1, sw - 2.2, sh - 2.2)
+        let mut time = (time % total as f64) as f32;
+        for frame in frames {
+            if time < frame.duration {
+                return frame.tile_id;
+            }
+            time -= frame.duration;
+        }
+        frames[frames.len() - 1].tile_id
     }
 }
 
 #[derive(Debug)]
 pub struct Map {
-    pub layers: HashMap<String, Layer>,
-    pub tilesets: HashMap<String, TileSet>,
+    /// All the layers, including the ones nested in groups.
+    /// Private so that every change goes through "layer_mut" or "set_tile",
+    /// which drop the cached vertices of the layer.
+    layers: HashMap<String, Layer>,
+    /// Top level layers, in drawing order
+    pub root_layers: Vec<String>,
+    /// Private for the same reason as "layers", changes go through "tileset_mut"
+    tilesets: HashMap<String, TileSet>,
+    pub properties: Properties,
+
+    pub orientation: Orientation,
+    pub render_order: RenderOrder,
+
+    /// Time in seconds used to pick the current frame of animated tiles.
+    /// f64 so frames stay steady however long the map has been running
+    pub time: f64,
 
     /// Deserialized json as is
     pub raw_tiled_map: tiled::Map,
+
+    /// Vertices of the tile layers drawn so far, rebuilt when their tiles change
+    batches: RefCell<HashMap<String, LayerBatch>>,
 }
 
 impl Map {
-    pub fn spr(&self, tileset: &str, sprite: u32, dest: Rect) {
-        if self.tilesets.contains_key(tileset) == false {
-            panic!(
-                "No such tileset: {}, tilesets available: {:?}",
-                tileset,
-                self.tilesets.keys()
-            )
+    /// Advance tile animations by "dt" seconds.
+    pub fn update(&mut self, dt: f32) {
+        self.time += dt as f64;
+    }
+
+    /// Animation frames of the given tile, None if the tile is not animated.
+    pub fn tile_animation(&self, tileset: &str, sprite: u32) -> Option<&[AnimationFrame]> {
+        self.tilesets
+            .get(tileset)
+            .and_then(|tileset| tileset.animations.get(&sprite))
+            .map(|frames| &frames[..])
+    }
+
+    /// Tile id that should currently be displayed in place of "sprite".
+    pub fn animated_sprite(&self, tileset: &str, sprite: u32) -> u32 {
+        self.tilesets
+            .get(tileset)
+            .map_or(sprite, |tileset| tileset.animated_sprite(sprite, self.time))
+    }
+
+    fn tileset(&self, tileset: &str) -> Result<&TileSet, Error> {
+        self.tilesets
+            .get(tileset)
+            .ok_or_else(|| Error::TilesetNotFound {
+                tileset: tileset.to_owned(),
+                available: self.tilesets.keys().cloned().collect(),
+            })
+    }
+
+    /// All the tilesets, by name
+    pub fn tilesets(&self) -> &HashMap<String, TileSet> {
+        &self.tilesets
+    }
+
+    /// Tileset to modify, the cached vertices of all the layers are rebuilt on the next draw
+    pub fn tileset_mut(&mut self, tileset: &str) -> Option<&mut TileSet> {
+        self.batches.borrow_mut().clear();
+        self.tilesets.get_mut(tileset)
+    }
+
+    /// All the layers, including the ones nested in groups
+    pub fn layers(&self) -> &HashMap<String, Layer> {
+        &self.layers
+    }
+
+    /// Layer to modify, its cached vertices are rebuilt on the next draw
+    pub fn layer_mut(&mut self, layer: &str) -> Option<&mut Layer> {
+        self.invalidate_layer(layer);
+        self.layers.get_mut(layer)
+    }
+
+    fn layer(&self, layer: &str) -> Result<&Layer, Error> {
+        self.layers.get(layer).ok_or_else(|| Error::LayerNotFound {
+            layer: layer.to_owned(),
+        })
+    }
+
+    fn tile_size(&self) -> Vec2 {
+        vec2(
+            self.raw_tiled_map.tilewidth as f32,
+            self.raw_tiled_map.tileheight as f32,
+        )
+    }
+
+    fn map_size(&self) -> (u32, u32) {
+        (self.raw_tiled_map.width, self.raw_tiled_map.height)
+    }
+
+    /// Top left corner of the bounding box of tile "x", "y", in map pixels.
+    /// Tiles bigger than the map tiles are drawn aligned to the bottom of this box.
+    pub fn tile_to_world(&self, x: i32, y: i32) -> Vec2 {
+        self.orientation
+            .tile_to_world(x, y, self.tile_size(), self.map_size())
+    }
+
+    /// Center of tile "x", "y", in map pixels
+    pub fn tile_center(&self, x: i32, y: i32) -> Vec2 {
+        self.tile_to_world(x, y) + self.tile_size() / 2.
+    }
+
+    /// Tile at map pixel "pos". The tile may be outside of the map.
+    pub fn world_to_tile(&self, pos: Vec2) -> (i32, i32) {
+        self.orientation
+            .world_to_tile(pos, self.tile_size(), self.map_size())
+    }
+
+    /// Size of the whole map in pixels
+    pub fn pixel_size(&self) -> Vec2 {
+        self.orientation
+            .pixel_size(self.tile_size(), self.map_size())
+    }
+
+    /// Source rect, in tiles, covering the whole map
+    fn full_source(&self) -> Rect {
+        let (width, height) = self.map_size();
+        Rect::new(0., 0., width as f32, height as f32)
+    }
+
+    /// Bounding box, in map pixels, of the tiles of "source", a rect in tiles.
+    /// Tile rows and columns are not aligned with the pixels of
+    /// isometric, staggered and hexagonal maps.
+    fn source_bounds(&self, source: Rect) -> Rect {
+        let (x0, y0) = (source.x as i32, source.y as i32);
+        let (x1, y1) = (
+            x0 + (source.w as i32 - 1).max(0),
+            y0 + (source.h as i32 - 1).max(0),
+        );
+        let corner = [(x0, y0), (x1, y0), (x0, y1), (x1, y1)]
+            .iter()
+            .map(|(x, y)| self.tile_to_world(*x, *y))
+            .fold(vec2(f32::MAX, f32::MAX), Vec2::min);
+        let size = self
+            .orientation
+            .pixel_size(self.tile_size(), (source.w as u32, source.h as u32));
+        Rect::new(corner.x, corner.y, size.x, size.y)
+    }
+
+    /// Check that "rect", in tiles, lies within the layer data
+    fn check_rect(name: &str, layer: &Layer, rect: Rect) -> Result<(), Error> {
+        if rect.w <= 0. || rect.h <= 0. {
+            return Ok(());
         }
-        let tileset = &self.tilesets[tileset];
-        let spr_rect = tileset.sprite_rect(sprite);
+
+        let x = rect.x as i64 + rect.w as i64 - 1;
+        let y = rect.y as i64 + rect.h as i64 - 1;
+        let len = layer.data.len();
+
+        if rect.x < 0.
+            || rect.y < 0.
+            || x >= layer.width as i64
+            || y >= layer.height as i64
+            || (y * layer.width as i64 + x) as usize >= len
+        {
+            return Err(Error::TileIndexOutOfRange {
+                layer: name.to_owned(),
+                index: (y * layer.width as i64 + x).max(0) as usize,
+                len,
+            });
+        }
+        Ok(())
+    }
+
+    pub fn spr(&self, tileset: &str, sprite: u32, dest: Rect) {
+        self.try_spr(tileset, sprite, dest)
+            .unwrap_or_else(|err| panic!("{}", err));
+    }
+
+    /// Same as "spr", but returns an error on unknown tileset or sprite.
+    pub fn try_spr(&self, tileset: &str, sprite: u32, dest: Rect) -> Result<(), Error> {
+        self.spr_color(tileset, sprite, dest, WHITE)
+    }
+
+    fn spr_color(&self, tileset: &str, sprite: u32, dest: Rect, color: Color) -> Result<(), Error> {
+        let name = tileset;
+        let tileset = self.tileset(tileset)?;
+        let (texture, spr_rect) = tileset.sprite(sprite).ok_or(Error::SpriteOutOfRange {
+            tileset: name.to_owned(),
+            sprite,
+            tilecount: tileset.tilecount,
+        })?;
 
         draw_texture_ex(
-            tileset.texture,
+            texture,
             dest.x,
             dest.y,
-            WHITE,
+            color,
             DrawTextureParams {
                 dest_size: Some(vec2(dest.w, dest.h)),
-                source: Some(Rect::new(
-                    spr_rect.x - 1.0,
-                    spr_rect.y - 1.0,
-                    spr_rect.w + 2.0,
-                    spr_rect.h + 2.0,
-                )),
+                source: Some(spr_rect),
                 ..Default::default()
             },
         );
+        Ok(())
+    }
+
+    /// Set the inset of all the tilesets, see TileSet::inset.
+    pub fn set_texture_inset(&mut self, inset: f32) {
+        for tileset in self.tilesets.values_mut() {
+            tileset.inset = inset;
+        }
+        self.batches.borrow_mut().clear();
+    }
+
+    /// Replace the tile in cell "x", "y" of a tile layer.
+    pub fn set_tile(
+        &mut self,
+        layer: &str,
+        x: u32,
+        y: u32,
+        tile: Option<Tile>,
+    ) -> Result<(), Error> {
+        let name = layer;
+        let layer = self
+            .layers
+            .get_mut(layer)
+            .ok_or_else(|| Error::LayerNotFound {
+                layer: name.to_owned(),
+            })?;
+
+        // in usize, u32 coordinates far out of the layer would overflow
+        let index = y as usize * layer.width as usize + x as usize;
+        let len = layer.data.len();
+        if x >= layer.width || y >= layer.height || index >= len {
+            return Err(Error::TileIndexOutOfRange {
+                layer: name.to_owned(),
+                index,
+                len,
+            });
+        }
+
+        layer.data[index] = tile;
+        self.invalidate_layer(name);
+        Ok(())
+    }
+
+    /// Tile "id" of "tileset", with its type and properties.
+    pub fn new_tile(&self, tileset: &str, id: u32) -> Result<Tile, Error> {
+        let name = tileset;
+        let tileset = self.tileset(tileset)?;
+        if tileset.sprite(id).is_none() {
+            return Err(Error::SpriteOutOfRange {
+                tileset: name.to_owned(),
+                sprite: id,
+                tilecount: tileset.tilecount,
+            });
+        }
+
+        Ok(tileset.tiles.get(&id).cloned().unwrap_or_else(|| Tile {
+            id,
+            tileset: name.to_owned(),
+            attrs: String::new(),
+            properties: Properties::default(),
+        }))
+    }
+
+    /// Wang set named "name" and the name of its tileset.
+    /// With several wang sets of the same name, an arbitrary one is returned.
+    pub fn wang_set(&self, name: &str) -> Option<(&str, &WangSet)> {
+        self.tilesets.iter().find_map(|(tileset_name, tileset)| {
+            tileset
+                .wang_sets
+                .iter()
+                .find(|wang_set| wang_set.name == name)
+                .map(|wang_set| (tileset_name.as_str(), wang_set))
+        })
+    }
+
+    /// Paint terrain "color" of wang set "wang_set" on cell "x", "y" of a tile layer.
+    /// The cell gets the tile of the wang set made of this terrain only, and the
+    /// surrounding cells get the transition tiles matching their current terrains.
+    /// Neighbour cells with tiles from outside of the wang set are not changed.
+    pub fn set_terrain(
+        &mut self,
+        layer: &str,
+        x: u32,
+        y: u32,
+        wang_set: &str,
+        color: &str,
+    ) -> Result<(), Error> {
+        let (tileset, set) = self
+            .wang_set(wang_set)
+            .ok_or_else(|| Error::WangSetNotFound {
+                wang_set: wang_set.to_owned(),
+            })?;
+        let color = set
+            .color_index(color)
+            .ok_or_else(|| Error::WangColorNotFound {
+                wang_set: wang_set.to_owned(),
+                color: color.to_owned(),
+            })?;
+        let (width, height) = {
+            let layer = self.layer(layer)?;
+            (layer.width as i32, layer.height as i32)
+        };
+
+        let mut changes = vec![];
+        for dy in -1..=1 {
+            for dx in -1..=1 {
+                let (cx, cy) = (x as i32 + dx, y as i32 + dy);
+                if cx < 0 || cy < 0 || cx >= width || cy >= height {
+                    continue;
+                }
+
+                let current = self
+                    .try_get_tile(layer, cx as u32, cy as u32)?
+                    .as_ref()
+                    .filter(|tile| tile.tileset == tileset)
+                    .and_then(|tile| set.wang_id(tile.id));
+                let mut wang_id = match current {
+                    Some(wang_id) => wang_id,
+                    None if (dx, dy) == (0, 0) => [0; 8],
+                    None => continue,
+                };
+
+                // slots of this cell touching the painted cell
+                let mut required = [false; 8];
+                for slot in wang::shared_slots((-dx, -dy)) {
+                    if set.uses_slot(*slot) {
+                        wang_id[*slot] = color;
+                        required[*slot] = true;
+                    }
+                }
+
+                if let Some(id) = set.best_tile(&wang_id, &required) {
+                    changes.push((cx as u32, cy as u32, id));
+                }
+            }
+        }
+
+        let tileset = tileset.to_owned();
+        for (x, y, id) in changes {
+            let tile = self.new_tile(&tileset, id)?;
+            self.set_tile(layer, x, y, Some(tile))?;
+        }
+        Ok(())
+    }
+
+    /// Drop the cached vertices of a tile layer
+    fn invalidate_layer(&self, layer: &str) {
+        self.batches.borrow_mut().remove(layer);
+    }
+
+    fn build_batch(&self, layer: &Layer) -> Result<LayerBatch, Error> {
+        let tile_size = self.tile_size();
+        let (up, left) = self.reversed_order();
+        let passes = self.row_passes();
+        let mut batch = LayerBatch::new(left);
+
+        let mut rows = (0..layer.height).collect::<Vec<_>>();
+        let mut columns = (0..layer.width).collect::<Vec<_>>();
+        if up {
+            rows.reverse();
+        }
+        if left {
+            columns.reverse();
+        }
+
+        for y in rows {
+            for pass in 0..passes {
+                for x in &columns {
+                    let x = *x;
+                    if passes > 1
+                        && self.orientation.second_pass(x as i32, tile_size) != Some(pass == 1)
+                    {
+                        continue;
+                    }
+                    let tile = match layer.data.get((y * layer.width + x) as usize) {
+                        Some(Some(tile)) => tile,
+                        _ => continue,
+                    };
+                    let sprite = self.animated_sprite(&tile.tileset, tile.id);
+                    let (texture, source, dest) = self.tile_quad(tile, sprite, x, y)?;
+
+                    if self.tile_animation(&tile.tileset, tile.id).is_some() {
+                        batch.animated.push(AnimatedQuad {
+                            quad: batch.quads(),
+                            x,
+                            y,
+                            sprite,
+                        });
+                    }
+                    batch.push(x, dest, texture, source);
+                }
+                batch.end_row();
+            }
+        }
+        Ok(batch)
+    }
+
+    /// Whether tile rows are drawn from the bottom up and columns from right to left,
+    /// according to the render order of orthogonal maps
+    fn reversed_order(&self) -> (bool, bool) {
+        if self.orientation != Orientation::Orthogonal {
+            return (false, false);
+        }
+        match self.render_order {
+            RenderOrder::RightDown => (false, false),
+            RenderOrder::RightUp => (true, false),
+            RenderOrder::LeftDown => (false, true),
+            RenderOrder::LeftUp => (true, true),
+        }
+    }
+
+    /// Rows of staggered X maps are drawn in two passes, see Orientation::second_pass
+    fn row_passes(&self) -> u32 {
+        match self.orientation.second_pass(0, self.tile_size()) {
+            Some(_) => 2,
+            None => 1,
+        }
+    }
+
+    /// Texture, source rect and destination rect in layer pixels of a tile in cell "x", "y"
+    fn tile_quad(
+        &self,
+        tile: &Tile,
+        sprite: u32,
+        x: u32,
+        y: u32,
+    ) -> Result<(Texture2D, Rect, Rect), Error> {
+        let tileset = self.tileset(&tile.tileset)?;
+        let (texture, source) = tileset.sprite(sprite).ok_or(Error::SpriteOutOfRange {
+            tileset: tile.tileset.clone(),
+            sprite,
+            tilecount: tileset.tilecount,
+        })?;
+        let pos = self.tile_to_world(x as i32, y as i32);
+        let tile_size = self.tile_size();
+        let cell = Rect::new(pos.x, pos.y, tile_size.x, tile_size.y);
+        Ok((
+            texture,
+            source,
+            self.tile_dest(&tile.tileset, sprite, cell)?,
+        ))
+    }
+
+    /// Draw the tiles in columns "x0..x1" and rows "y0..y1" of a tile layer seen
+    /// by the active camera, with the layer pixel (0, 0) at "origin", scaled by "scale"
+    /// and multiplied by "color".
+    /// Reuses the cached vertices of the layer, built on first use.
+    fn draw_batch(
+        &self,
+        name: &str,
+        (x0, x1): (u32, u32),
+        (y0, y1): (u32, u32),
+        origin: Vec2,
+        scale: Vec2,
+        color: Color,
+    ) -> Result<(), Error> {
+        let layer = self.layer(name)?;
+        let mut batches = self.batches.borrow_mut();
+
+        let mut rebuild = !batches.contains_key(name);
+
+        if let (false, Some(batch)) = (rebuild, batches.get_mut(name)) {
+            for ix in 0..batch.animated.len() {
+                let animated = batch.animated[ix];
+                let tile = match &layer.data[(animated.y * layer.width + animated.x) as usize] {
+                    Some(tile) => tile,
+                    None => continue,
+                };
+                let sprite = self.animated_sprite(&tile.tileset, tile.id);
+                if sprite == animated.sprite {
+                    continue;
+                }
+
+                let (texture, source, dest) =
+                    self.tile_quad(tile, sprite, animated.x, animated.y)?;
+                batch.animated[ix].sprite = sprite;
+                // frames from another texture break the texture runs
+                if !batch.replace(animated.quad, dest, texture, source) {
+                    rebuild = true;
+                    break;
+                }
+            }
+        }
+
+        if rebuild {
+            batches.insert(name.to_owned(), self.build_batch(layer)?);
+        }
+
+        // the part of the layer seen by the camera, in layer pixels
+        let camera = camera_view();
+        let corners = [
+            (camera.point() - origin) / scale,
+            (camera.point() + camera.size() - origin) / scale,
+        ];
+        let view = corners[0].min(corners[1]);
+        let (first, last) = self.visible_tiles(view, (corners[0] - corners[1]).abs());
+        // tiles bigger than the grid reach into the view from the cells below
+        let tile_size = self.tile_size();
+        let overhang = self.tilesets.values().fold(1., |overhang: f32, tileset| {
+            overhang.max((tileset.tileheight as f32 / tile_size.y).ceil())
+        });
+        let (x0, x1) = (
+            x0.max(first.x.max(0.) as u32),
+            x1.min((last.x + 1.).max(0.) as u32),
+        );
+        let (y0, y1) = (
+            y0.max(first.y.max(0.) as u32),
+            y1.min((last.y + overhang).max(0.) as u32),
+        );
+        if x0 >= x1 || y0 >= y1 {
+            return Ok(());
+        }
+
+        let passes = self.row_passes() as usize;
+        let rows = if self.reversed_order().0 {
+            (layer.height - y1.min(layer.height)) as usize * passes
+                ..(layer.height - y0.min(layer.height)) as usize * passes
+        } else {
+            y0 as usize * passes..y1 as usize * passes
+        };
+        let model = Mat4::from_translation(vec3(origin.x, origin.y, 0.))
+            * Mat4::from_scale(vec3(scale.x, scale.y, 1.));
+        if let Some(batch) = batches.get_mut(name) {
+            batch.draw(rows, x0, x1, model, color);
+        }
+        Ok(())
+    }
+
+    /// Where a tile placed in the map cell "cell" is drawn.
+    /// Tiles bigger than the map cells are aligned to the bottom left corner
+    /// of the cell, as in tiled, and tileset "tileoffset" is applied.
+    fn tile_dest(&self, tileset: &str, sprite: u32, cell: Rect) -> Result<Rect, Error> {
+        let tileset = self.tileset(tileset)?;
+        let scale = vec2(
+            cell.w / self.raw_tiled_map.tilewidth as f32,
+            cell.h / self.raw_tiled_map.tileheight as f32,
+        );
+        let size = tileset.sprite_size(sprite) * scale;
+        let offset = tileset.tileoffset * scale;
+
+        Ok(Rect::new(
+            cell.x + offset.x,
+            cell.y + cell.h - size.y + offset.y,
+            size.x,
+            size.y,
+        ))
     }
 
     pub fn spr_ex(&self, tileset: &str, source: Rect, dest: Rect) {
-        let tileset = &self.tilesets[tileset];
+        self.try_spr_ex(tileset, source, dest)
+            .unwrap_or_else(|err| panic!("{}", err));
+    }
+
+    /// Same as "spr_ex", but returns an error on unknown tileset.
+    pub fn try_spr_ex(&self, tileset: &str, source: Rect, dest: Rect) -> Result<(), Error> {
+        let tileset = self.tileset(tileset)?;
 
         draw_texture_ex(
             tileset.texture,
@@ -124,66 +716,350 @@ impl Map {
                 ..Default::default()
             },
         );
+        Ok(())
     }
 
     pub fn contains_layer(&self, layer: &str) -> bool {
         self.layers.contains_key(layer)
     }
 
+    /// Draw the tiles of a layer as is, without its offset, opacity or visibility,
+    /// use "draw_layer" for that.
+    /// "source" is the rect of the layer to draw, in tiles.
     pub fn draw_tiles(&self, layer: &str, dest: Rect, source: impl Into<Option<Rect>>) {
-        assert!(self.layers.contains_key(layer), "No such layer: {}", layer);
+        self.try_draw_tiles(layer, dest, source)
+            .unwrap_or_else(|err| panic!("{}", err));
+    }
 
-        let source = source.into().unwrap_or(Rect::new(
-            0.,
-            0.,
-            self.raw_tiled_map.width as f32,
-            self.raw_tiled_map.height as f32,
-        ));
-        let layer = &self.layers[layer];
+    /// Same as "draw_tiles", but returns an error on unknown layer or
+    /// "source" outside of the layer.
+    pub fn try_draw_tiles(
+        &self,
+        layer: &str,
+        dest: Rect,
+        source: impl Into<Option<Rect>>,
+    ) -> Result<(), Error> {
+        let name = layer;
+        let source = source.into().unwrap_or_else(|| self.full_source());
+        let layer = self.layer(layer)?;
+        Self::check_rect(name, layer, source)?;
+
+        if source.w <= 0. || source.h <= 0. {
+            return Ok(());
+        }
+
+        let placement = self.placement(dest, source);
+        let (x, y) = (source.x as u32, source.y as u32);
+
+        self.draw_batch(
+            name,
+            (x, x + source.w as u32),
+            (y, y + source.h as u32),
+            placement.origin(),
+            placement.scale,
+            WHITE,
+        )
+    }
+
+    /// Draw a layer with its offset, opacity, tint and parallax factor,
+    /// combined with the ones of its parent groups.
+    /// Nothing is drawn if the layer or one of its parents is not visible.
+    /// Groups draw all their children, object layers are not drawn.
+    /// "dest" and "source" work the same way as in "draw_tiles", with parallax
+    /// applied to the "source" position.
+    pub fn draw_layer(&self, layer: &str, dest: Rect, source: impl Into<Option<Rect>>) {
+        self.try_draw_layer(layer, dest, source)
+            .unwrap_or_else(|err| panic!("{}", err));
+    }
+
+    /// Same as "draw_layer", but returns an error on unknown layer.
+    /// Parts of "source" outside of the layer are not drawn.
+    pub fn try_draw_layer(
+        &self,
+        layer: &str,
+        dest: Rect,
+        source: impl Into<Option<Rect>>,
+    ) -> Result<(), Error> {
+        let source = source.into().unwrap_or_else(|| self.full_source());
+        self.draw_layer_at(layer, self.placement(dest, source))
+    }
+
+    /// Draw all the layers of the map in tiled order with "draw_layer".
+    pub fn draw(&self, dest: Rect, source: impl Into<Option<Rect>>) {
+        let source = source.into();
+        for layer in &self.root_layers {
+            self.draw_layer(layer, dest, source);
+        }
+    }
 
-        let spr_width = dest.w / source.w;
-        let spr_height = dest.h / source.h;
+    /// Placement drawing "source", in tiles, to "dest"
+    fn placement(&self, dest: Rect, source: Rect) -> Placement {
+        let bounds = self.source_bounds(source);
+        Placement {
+            dest,
+            view: bounds.point(),
+            scale: dest.size() / bounds.size(),
+        }
+    }
+
+    /// Same as "draw", with the map placed in pixels rather than in tiles
+    pub(crate) fn draw_at(&self, placement: Placement) {
+        for layer in &self.root_layers {
+            self.draw_layer_at(layer, placement)
+                .unwrap_or_else(|err| panic!("{}", err));
+        }
+    }
+
+    /// Same as "try_draw_layer", with the map placed in pixels rather than in tiles
+    pub(crate) fn draw_layer_at(&self, layer: &str, placement: Placement) -> Result<(), Error> {
+        let mut parents = vec![];
+        let mut parent = self.layer(layer)?.parent.as_ref();
+        while let Some(name) = parent {
+            parents.push(name);
+            parent = self.layers[name].parent.as_ref();
+        }
+
+        let mut state = LayerState::default();
+        for parent in parents.iter().rev() {
+            match state.apply(&self.layers[*parent]) {
+                Some(parent_state) => state = parent_state,
+                None => return Ok(()),
+            }
+        }
+
+        self.draw_layer_state(layer, placement, state)
+    }
+
+    /// Range of tiles covering the "size" pixels from "view", in tiles.
+    /// The end of the range is exclusive, and may be outside of the map.
+    fn visible_tiles(&self, view: Vec2, size: Vec2) -> (Vec2, Vec2) {
+        let tile_size = self.tile_size();
+        if self.orientation == Orientation::Orthogonal {
+            return (
+                (view / tile_size).floor(),
+                ((view + size) / tile_size).ceil(),
+            );
+        }
+
+        let corners = [
+            view,
+            view + vec2(size.x, 0.),
+            view + vec2(0., size.y),
+            view + size,
+        ]
+        .iter()
+        .map(|corner| {
+            let (x, y) = self.world_to_tile(*corner);
+            vec2(x as f32, y as f32)
+        })
+        .collect::<Vec<_>>();
+
+        let first = corners
+            .iter()
+            .fold(corners[0], |first, tile| first.min(*tile));
+        let last = corners
+            .iter()
+            .fold(corners[0], |last, tile| last.max(*tile));
+        // tiles taller than the grid reach into the view from below
+        (first - vec2(1., 1.), last + vec2(3., 3.))
+    }
+
+    fn draw_layer_state(
+        &self,
+        name: &str,
+        placement: Placement,
+        parent: LayerState,
+    ) -> Result<(), Error> {
+        let layer = self.layer(name)?;
+        let state = match parent.apply(layer) {
+            Some(state) => state,
+            None => return Ok(()),
+        };
+
+        let Placement { dest, scale, .. } = placement;
+        // top left corner of the visible part of the layer, in layer pixels
+        let view = placement.view * state.parallax - state.offset;
+        let color = Color::new(
+            state.tint.r,
+            state.tint.g,
+            state.tint.b,
+            state.tint.a * state.opacity,
+        );
 
-        for y in source.y as u32..source.y as u32 + source.h as u32 {
-            for x in source.x as u32..source.x as u32 + source.w as u32 {
-                let pos = vec2(
-                    (x - source.x as u32) as f32 / source.w * dest.w + dest.x,
-                    (y - source.y as u32) as f32 / source.h * dest.h + dest.y,
+        match layer.ty {
+            LayerType::Tiles => {
+                let (first, last) = self.visible_tiles(view, placement.size());
+                let (x0, x1) = (
+                    first.x.max(0.) as u32,
+                    last.x.min(layer.width as f32).max(0.) as u32,
+                );
+                let (y0, y1) = (
+                    first.y.max(0.) as u32,
+                    last.y.min(layer.height as f32).max(0.) as u32,
                 );
 
-                if let Some(tile) = &layer.data[(y * layer.width + x) as usize] {
-                    self.spr(
-                        &tile.tileset,
-                        tile.id,
-                        Rect::new(pos.x, pos.y, spr_width, spr_height),
-                    );
+                self.draw_batch(
+                    name,
+                    (x0, x1),
+                    (y0, y1),
+                    dest.point() - view * scale,
+                    scale,
+                    color,
+                )?;
+            }
+            LayerType::Image => {
+                let image = match &layer.image {
+                    Some(image) => image,
+                    None => return Ok(()),
+                };
+                let size = vec2(image.texture.width(), image.texture.height()) * scale;
+                if size.x <= 0. || size.y <= 0. {
+                    return Ok(());
+                }
+
+                let mut pos = dest.point() - view * scale;
+                if image.repeat_x {
+                    pos.x -= ((pos.x - dest.x) / size.x).ceil() * size.x;
+                }
+                if image.repeat_y {
+                    pos.y -= ((pos.y - dest.y) / size.y).ceil() * size.y;
+                }
+
+                let mut y = pos.y;
+                loop {
+                    let mut x = pos.x;
+                    loop {
+                        draw_texture_ex(
+                            image.texture,
+                            x,
+                            y,
+                            color,
+                            DrawTextureParams {
+                                dest_size: Some(size),
+                                ..Default::default()
+                            },
+                        );
+                        x += size.x;
+                        if !image.repeat_x || x >= dest.x + dest.w {
+                            break;
+                        }
+                    }
+                    y += size.y;
+                    if !image.repeat_y || y >= dest.y + dest.h {
+                        break;
+                    }
                 }
             }
+            LayerType::Group => {
+                for child in &layer.children {
+                    self.draw_layer_state(child, placement, state)?;
+                }
+            }
+            LayerType::Objects => {}
         }
+        Ok(())
+    }
+
+    pub fn tiles(&self, layer: &str, rect: impl Into<Option<Rect>>) -> TilesIterator<'_> {
+        self.try_tiles(layer, rect)
+            .unwrap_or_else(|err| panic!("{}", err))
     }
 
-    pub fn tiles(&self, layer: &str, rect: impl Into<Option<Rect>>) -> TilesIterator {
-        assert!(self.layers.contains_key(layer), "No such layer: {}", layer);
+    /// Same as "tiles", but returns an error on unknown layer or "rect"
+    /// outside of the layer.
+    pub fn try_tiles(
+        &self,
+        layer: &str,
+        rect: impl Into<Option<Rect>>,
+    ) -> Result<TilesIterator<'_>, Error> {
+        let name = layer;
+        let rect = rect.into().unwrap_or_else(|| self.full_source());
+        let layer = self.layer(layer)?;
+        Self::check_rect(name, layer, rect)?;
 
-        let rect = rect.into().unwrap_or(Rect::new(
-            0.,
-            0.,
-            self.raw_tiled_map.width as f32,
-            self.raw_tiled_map.height as f32,
-        ));
-        TilesIterator::new(&self.layers[layer], rect)
+        Ok(TilesIterator::new(layer, rect))
     }
 
     pub fn get_tile(&self, layer: &str, x: u32, y: u32) -> &Option<Tile> {
-        assert!(self.layers.contains_key(layer), "No such layer: {}", layer);
+        self.try_get_tile(layer, x, y)
+            .unwrap_or_else(|err| panic!("{}", err))
+    }
 
-        let layer = &self.layers[layer];
+    /// Same as "get_tile", but returns an error on unknown layer.
+    /// Tiles outside of the layer are None.
+    pub fn try_get_tile(&self, layer: &str, x: u32, y: u32) -> Result<&Option<Tile>, Error> {
+        let layer = self.layer(layer)?;
 
         if x >= layer.width || y >= layer.height {
-            return &None;
+            return Ok(&None);
+        }
+
+        Ok(layer
+            .data
+            .get(y as usize * layer.width as usize + x as usize)
+            .unwrap_or(&None))
+    }
+}
+
+/// Where a map is drawn: map pixel "view" lands on the top left corner of "dest",
+/// and each map pixel is "scale" screen units wide.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct Placement {
+    pub dest: Rect,
+    pub view: Vec2,
+    pub scale: Vec2,
+}
+
+impl Placement {
+    /// Screen position of map pixel (0, 0)
+    pub fn origin(&self) -> Vec2 {
+        self.dest.point() - self.view * self.scale
+    }
+
+    /// Size of the visible part of the map, in map pixels
+    fn size(&self) -> Vec2 {
+        self.dest.size() / self.scale
+    }
+}
+
+/// Drawing state accumulated through the group hierarchy
+#[derive(Debug, Clone, Copy)]
+struct LayerState {
+    offset: Vec2,
+    opacity: f32,
+    parallax: Vec2,
+    tint: Color,
+}
+
+impl Default for LayerState {
+    fn default() -> LayerState {
+        LayerState {
+            offset: vec2(0., 0.),
+            opacity: 1.,
+            parallax: vec2(1., 1.),
+            tint: WHITE,
+        }
+    }
+}
+
+impl LayerState {
+    /// State for drawing "layer" inside of self, None if the layer is hidden.
+    fn apply(&self, layer: &Layer) -> Option<LayerState> {
+        if !layer.visible {
+            return None;
         }
 
-        &layer.data[(y * layer.width + x) as usize]
+        Some(LayerState {
+            offset: self.offset + layer.offset,
+            opacity: self.opacity * layer.opacity,
+            parallax: self.parallax * layer.parallax,
+            tint: Color::new(
+                self.tint.r * layer.tint.r,
+                self.tint.g * layer.tint.g,
+                self.tint.b * layer.tint.b,
+                self.tint.a * layer.tint.a,
+            ),
+        })
     }
 }
 
@@ -209,28 +1085,19 @@ impl<'a> Iterator for TilesIterator<'a> {
     type Item = (u32, u32, &'a Option<Tile>);
 
     fn next(&mut self) -> Option<Self::Item> {
-        let next_x;
-        let next_y;
-
-        if self.current.0 + 1 >= self.rect.x as u32 + self.rect.w as u32 {
-            next_x = self.rect.x as u32;
-            next_y = self.current.1 + 1;
-        } else {
-            next_x = self.current.0 + 1;
-            next_y = self.current.1;
-        }
-
-        if next_y >= self.rect.y as u32 + self.rect.h as u32 {
+        let (x, y) = self.current;
+        let right = self.rect.x as u32 + self.rect.w as u32;
+        let bottom = self.rect.y as u32 + self.rect.h as u32;
+        if x >= right || y >= bottom {
             return None;
         }
 
-        let res = Some((
-            self.current.0,
-            self.current.1,
-            &self.layer.data[(self.current.1 * self.layer.width + self.current.0) as usize],
-        ));
-        self.current = (next_x, next_y);
-        res
+        self.current = if x + 1 >= right {
+            (self.rect.x as u32, y + 1)
+        } else {
+            (x + 1, y)
+        };
+        Some((x, y, &self.layer.data[(y * self.layer.width + x) as usize]))
     }
 }
 
@@ -243,120 +1110,471 @@ pub fn load_map(
     data: &str,
     textures: &[(&str, Texture2D)],
     external_tilesets: &[(&str, &str)],
+) -> Result<Map, error::Error> {
+    load_map_with_templates(data, textures, external_tilesets, &[])
+}
+
+/// Same as "load_map", for maps with template object instances.
+/// "templates" is a map of template file name, as referenced by objects, to template json content.
+pub fn load_map_with_templates(
+    data: &str,
+    textures: &[(&str, Texture2D)],
+    external_tilesets: &[(&str, &str)],
+    templates: &[(&str, &str)],
 ) -> Result<Map, error::Error> {
     let map: tiled::Map = DeJson::deserialize_json(data)?;
 
+    let templates = templates
+        .iter()
+        .map(|(name, data)| Ok((*name, DeJson::deserialize_json(data)?)))
+        .collect::<Result<Vec<(&str, tiled::Template)>, error::Error>>()?;
+
     let mut layers = HashMap::new();
     let mut tilesets = HashMap::new();
     let mut map_tilesets = vec![];
 
     for tileset in &map.tilesets {
-        let tileset = if tileset.source.is_empty() {
-            tileset.clone()
-        } else {
+        let tileset = if let Some(source) = &tileset.source {
             let tileset_data = external_tilesets
                 .iter()
-                .find(|(name, _)| *name == &tileset.source)
-                .unwrap();
-            let mut map_tileset: tiled::Tileset = DeJson::deserialize_json(&tileset_data.1)?;
+                .find(|(name, _)| name == source)
+                .ok_or_else(|| error::Error::ExternalTilesetNotFound {
+                    tileset: source.clone(),
+                })?;
+            let mut map_tileset: tiled::Tileset = DeJson::deserialize_json(tileset_data.1)?;
             map_tileset.firstgid = tileset.firstgid;
             map_tileset
+        } else {
+            tileset.clone()
         };
 
-        let texture = textures
-            .iter()
-            .find(|(name, _)| *name == tileset.image)
-            .ok_or(error::Error::TextureNotFound {
-                texture: tileset.image.clone(),
-            })?
-            .1;
+        let find_texture = |image: &str| {
+            textures
+                .iter()
+                .find(|(name, _)| *name == image)
+                .map(|(_, texture)| *texture)
+                .ok_or(error::Error::TextureNotFound {
+                    texture: image.to_owned(),
+                })
+        };
+
+        // image collection tilesets have no image of their own
+        let texture = if tileset.image.is_empty() {
+            Texture2D::empty()
+        } else {
+            find_texture(&tileset.image)?
+        };
+
+        let mut tile_images = HashMap::new();
+        for tile in &tileset.tiles {
+            if let Some(image) = tile.image.as_ref().filter(|image| !image.is_empty()) {
+                tile_images.insert(tile.id as u32, find_texture(image)?);
+            }
+        }
 
         tilesets.insert(
             tileset.name.clone(),
             TileSet {
                 texture,
+                tile_images,
+                firstgid: tileset.firstgid,
                 columns: tileset.columns as _,
                 margin: tileset.margin,
                 spacing: tileset.spacing,
                 tilewidth: tileset.tilewidth,
                 tileheight: tileset.tileheight,
+                tilecount: tileset.tilecount,
+                tileoffset: tileset.tileoffset.as_ref().map_or(vec2(0., 0.), |offset| {
+                    vec2(offset.x as f32, offset.y as f32)
+                }),
+                inset: 0.,
+                animations: tileset
+                    .tiles
+                    .iter()
+                    .filter(|tile| !tile.animation.is_empty())
+                    .map(|tile| {
+                        let frames = tile
+                            .animation
+                            .iter()
+                            .map(|frame| AnimationFrame {
+                                tile_id: frame.tileid as u32,
+                                duration: frame.duration as f32 / 1000.,
+                            })
+                            .collect();
+                        (tile.id as u32, frames)
+                    })
+                    .collect(),
+                tiles: tileset
+                    .tiles
+                    .iter()
+                    .filter(|tile| tile.ty.is_some() || !tile.properties.is_empty())
+                    .map(|tile| {
+                        let id = tile.id as u32;
+                        let tile = Tile {
+                            id,
+                            tileset: tileset.name.clone(),
+                            attrs: tile.ty.clone().unwrap_or_default(),
+                            properties: Properties::from_raw(&tile.properties),
+                        };
+                        (id, tile)
+                    })
+                    .collect(),
+                wang_sets: tileset
+                    .wangsets
+                    .iter()
+                    .map(WangSet::from_raw)
+                    .chain(WangSet::from_terrains(&tileset))
+                    .collect(),
+                properties: Properties::from_raw(&tileset.properties),
             },
         );
 
         map_tilesets.push(tileset);
     }
 
-    for layer in &map.layers {
+    let root_layers = load_layers(
+        &map.layers,
+        None,
+        &map,
+        &map_tilesets,
+        textures,
+        &templates,
+        &mut layers,
+    )?;
+
+    Ok(Map {
+        layers,
+        root_layers,
+        tilesets,
+        properties: Properties::from_raw(&map.properties),
+        orientation: Orientation::from_raw(&map),
+        render_order: RenderOrder::from_raw(&map.renderorder),
+        time: 0.,
+        raw_tiled_map: map,
+        batches: RefCell::new(HashMap::new()),
+    })
+}
+
+/// Tile gids have their three highest bits used as flipping flags
+const GID_MASK: u32 = 0x1fff_ffff;
+
+/// Load "raw_layers" and all their children into "layers".
+/// Returns the names of "raw_layers".
+fn load_layers(
+    raw_layers: &[tiled::layer::Layer],
+    parent: Option<&str>,
+    map: &tiled::Map,
+    map_tilesets: &[tiled::Tileset],
+    textures: &[(&str, Texture2D)],
+    templates: &[(&str, tiled::Template)],
+    layers: &mut HashMap<String, Layer>,
+) -> Result<Vec<String>, error::Error> {
+    let mut names = vec![];
+
+    for layer in raw_layers {
         if layers.contains_key(&layer.name) {
             return Err(error::Error::NonUniqueLayerName {
                 layer: layer.name.clone(),
             });
         }
 
-        let tile_width = map.tilewidth as f32;
+        let ty = match layer.ty.as_str() {
+            "objectgroup" => LayerType::Objects,
+            "imagelayer" => LayerType::Image,
+            "group" => LayerType::Group,
+            _ => LayerType::Tiles,
+        };
+
+        // objects of isometric maps are placed on a grid of tileheight squares
+        let tile_width = if map.orientation == "isometric" {
+            map.tileheight as f32
+        } else {
+            map.tilewidth as f32
+        };
         let tile_height = map.tileheight as f32;
 
         let mut objects = vec![];
         for object in &layer.objects {
-            objects.push(Object {
-                gid: object.gid,
-                world_x: object.x,
-                world_y: object.y,
-                world_w: object.width,
-                world_h: object.height,
-
-                tile_x: (object.x / tile_width) as u32,
-                tile_y: (object.y / tile_height) as u32,
-                tile_w: (object.width / tile_width) as u32,
-                tile_h: (object.height / tile_height) as u32,
-                name: object.name.clone(),
-                properties: object
-                    .properties
-                    .iter()
-                    .map(|property| (property.name.to_string(), property.value.to_string()))
-                    .collect(),
-            });
+            let object = match &object.template {
+                Some(template) => {
+                    let template = templates.iter().find(|(name, _)| name == template).ok_or(
+                        error::Error::TemplateNotFound {
+                            template: template.clone(),
+                        },
+                    )?;
+                    object::apply_template(object, &template.1, &map.tilesets)
+                }
+                None => object.clone(),
+            };
+            objects.push(Object::from_raw(&object, tile_width, tile_height));
         }
 
         let find_tileset = |tile: u32| {
             map_tilesets.iter().find(|tileset| {
-                tile >= tileset.firstgid && tile < tileset.firstgid + tileset.tilecount
+                // image collection tilesets may have gaps in their tile ids
+                let count = tileset
+                    .tiles
+                    .iter()
+                    .map(|tile| tile.id as u32 + 1)
+                    .fold(tileset.tilecount, u32::max);
+                tile >= tileset.firstgid && tile < tileset.firstgid + count
+            })
+        };
+
+        if ty == LayerType::Tiles && layer.data.len() < (layer.width * layer.height) as usize {
+            return Err(error::Error::LayerDataLength {
+                layer: layer.name.clone(),
+                expected: (layer.width * layer.height) as usize,
+                len: layer.data.len(),
+            });
+        }
+        if let Some(gid) = layer
+            .data
+            .iter()
+            .map(|tile| tile & GID_MASK)
+            .find(|tile| *tile != 0 && find_tileset(*tile).is_none())
+        {
+            return Err(error::Error::InvalidTileGid {
+                layer: layer.name.clone(),
+                gid,
+            });
+        }
+
+        let image = if ty == LayerType::Image && !layer.image.is_empty() {
+            let texture = textures
+                .iter()
+                .find(|(name, _)| *name == layer.image)
+                .ok_or(error::Error::TextureNotFound {
+                    texture: layer.image.clone(),
+                })?
+                .1;
+            Some(ImageLayer {
+                texture,
+                repeat_x: layer.repeatx,
+                repeat_y: layer.repeaty,
             })
+        } else {
+            None
         };
 
+        let children = load_layers(
+            &layer.layers,
+            Some(&layer.name),
+            map,
+            map_tilesets,
+            textures,
+            templates,
+            layers,
+        )?;
+        // a child of the group may have taken its name
+        if layers.contains_key(&layer.name) {
+            return Err(error::Error::NonUniqueLayerName {
+                layer: layer.name.clone(),
+            });
+        }
+
         layers.insert(
             layer.name.clone(),
             Layer {
+                ty,
                 objects,
                 width: layer.width,
                 height: layer.height,
                 data: layer
                     .data
                     .iter()
+                    .map(|tile| tile & GID_MASK)
                     .map(|tile| {
-                        find_tileset(*tile).map(|tileset| {
-                            let attrs = tileset
+                        find_tileset(tile).map(|tileset| {
+                            let tile_data = tileset
                                 .tiles
                                 .iter()
-                                .find(|t| t.id as u32 == *tile - tileset.firstgid)
+                                .find(|t| t.id as u32 == tile - tileset.firstgid);
+                            let attrs = tile_data
                                 .and_then(|tile| tile.ty.clone())
                                 .unwrap_or("".to_owned());
+                            let properties = tile_data
+                                .map(|tile| Properties::from_raw(&tile.properties))
+                                .unwrap_or_default();
 
                             Tile {
-                                id: *tile - tileset.firstgid,
+                                id: tile - tileset.firstgid,
                                 tileset: tileset.name.clone(),
                                 attrs,
+                                properties,
                             }
                         })
                     })
                     .collect::<Vec<_>>(),
+                properties: Properties::from_raw(&layer.properties),
+                visible: layer.visible,
+                opacity: layer.opacity,
+                offset: vec2(
+                    layer.offsetx.unwrap_or(0) as f32,
+                    layer.offsety.unwrap_or(0) as f32,
+                ),
+                parallax: vec2(layer.parallaxx.unwrap_or(1.), layer.parallaxy.unwrap_or(1.)),
+                tint: layer
+                    .tintcolor
+                    .as_deref()
+                    .and_then(properties::parse_color)
+                    .unwrap_or(WHITE),
+                image,
+                parent: parent.map(|parent| parent.to_owned()),
+                children,
             },
         );
+        names.push(layer.name.clone());
     }
 
-    Ok(Map {
-        layers,
-        tilesets,
-        raw_tiled_map: map,
-    })
+    Ok(names)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A 2x1 map with a 4 tiles tileset and the given layers
+    fn load(layers: &str) -> Result<Map, Error> {
+        let data = format!(
+            r#"{{"width":2,"height":1,"tilewidth":8,"tileheight":8,"layers":{},
+            "tilesets":[{{"firstgid":1,"name":"ts","image":"ts.png","columns":2,"tilecount":4,"tilewidth":8,"tileheight":8}}]}}"#,
+            layers
+        );
+        load_map(&data, &[("ts.png", Texture2D::empty())], &[])
+    }
+
+    #[test]
+    fn isometric_tiles() {
+        let data = r#"{"width":10,"height":2,"tilewidth":32,"tileheight":16,"orientation":"isometric",
+            "layers":[{"name":"a","type":"tilelayer","width":10,"height":2,"data":[1,1,1,1,1,1,1,1,1,1,1,1,1,1,1,1,1,1,1,1]}],
+            "tilesets":[{"firstgid":1,"name":"ts","image":"ts.png","columns":1,"tilecount":1,"tilewidth":32,"tileheight":16}]}"#;
+        let map = load_map(data, &[("ts.png", Texture2D::empty())], &[]).unwrap();
+
+        assert_eq!(map.try_tiles("a", None).unwrap().count(), 20);
+        // the whole map is drawn to "dest" when there is no source rect
+        assert_eq!(
+            map.source_bounds(map.full_source()),
+            Rect::new(0., 0., 192., 96.)
+        );
+    }
+
+    /// A 3x3 grass map with a corner wang set of the 16 tiles mixing grass and water.
+    /// Bit n of a tile id tells whether corner n, clockwise from the top right, is water.
+    fn terrain_map() -> Map {
+        let wang_tiles = (0..16)
+            .map(|id| {
+                let corner = |bit: u32| 1 + (id >> bit & 1);
+                format!(
+                    r#"{{"tileid":{},"wangid":[0,{},0,{},0,{},0,{}]}}"#,
+                    id,
+                    corner(0),
+                    corner(1),
+                    corner(2),
+                    corner(3)
+                )
+            })
+            .collect::<Vec<_>>()
+            .join(",");
+        let data = format!(
+            r##"{{"width":3,"height":3,"tilewidth":8,"tileheight":8,
+            "layers":[{{"name":"g","type":"tilelayer","width":3,"height":3,"data":[1,1,1,1,1,1,1,1,1]}}],
+            "tilesets":[{{"firstgid":1,"name":"ts","image":"ts.png","columns":4,"tilecount":16,"tilewidth":8,"tileheight":8,
+              "wangsets":[{{"name":"ground","type":"corner","wangtiles":[{}],
+                "colors":[{{"name":"grass","color":"#00ff00","tile":0,"probability":1}},
+                          {{"name":"water","color":"#0000ff","tile":15,"probability":1}}]}}]}}]}}"##,
+            wang_tiles
+        );
+        load_map(&data, &[("ts.png", Texture2D::empty())], &[]).unwrap()
+    }
+
+    fn tile_ids(map: &Map) -> Vec<u32> {
+        map.tiles("g", None)
+            .map(|(_, _, tile)| tile.as_ref().unwrap().id)
+            .collect()
+    }
+
+    #[test]
+    fn terrain_transitions() {
+        let mut map = terrain_map();
+
+        map.set_terrain("g", 1, 1, "ground", "water").unwrap();
+        // each neighbour gets water on the corners it shares with the center
+        assert_eq!(
+            tile_ids(&map),
+            [0b0010, 0b0110, 0b0100, 0b0011, 0b1111, 0b1100, 0b0001, 0b1001, 0b1000]
+        );
+
+        map.set_terrain("g", 2, 1, "ground", "water").unwrap();
+        assert_eq!(
+            tile_ids(&map),
+            [0b0010, 0b0110, 0b0110, 0b0011, 0b1111, 0b1111, 0b0001, 0b1001, 0b1001]
+        );
+
+        assert!(matches!(
+            map.set_terrain("g", 0, 0, "ground", "lava"),
+            Err(Error::WangColorNotFound { .. })
+        ));
+        assert!(matches!(
+            map.set_terrain("g", 0, 0, "road", "water"),
+            Err(Error::WangSetNotFound { .. })
+        ));
+    }
+
+    #[test]
+    fn set_tile_out_of_the_layer() {
+        let mut map =
+            load(r#"[{"name":"a","type":"tilelayer","width":2,"height":1,"data":[1,2]}]"#).unwrap();
+        let tile = map.new_tile("ts", 3).unwrap();
+
+        // (0, 1) would land on index 2, past the end of the data
+        for (x, y) in [(2, 0), (0, 1), (u32::MAX, u32::MAX)] {
+            assert!(matches!(
+                map.set_tile("a", x, y, Some(tile.clone())),
+                Err(Error::TileIndexOutOfRange { len: 2, .. })
+            ));
+        }
+
+        map.set_tile("a", 1, 0, Some(tile)).unwrap();
+        let ids = map
+            .tiles("a", None)
+            .map(|(_, _, tile)| tile.as_ref().unwrap().id)
+            .collect::<Vec<_>>();
+        assert_eq!(ids, [0, 3]);
+    }
+
+    #[test]
+    fn tileset_changes_rebuild_batches() {
+        let mut map =
+            load(r#"[{"name":"a","type":"tilelayer","width":2,"height":1,"data":[1,2]}]"#).unwrap();
+        let batch = map.build_batch(&map.layers["a"]).unwrap();
+        map.batches.borrow_mut().insert("a".to_owned(), batch);
+
+        map.tileset_mut("ts").unwrap().inset = 0.5;
+        assert!(map.batches.borrow().is_empty());
+        assert_eq!(map.tilesets()["ts"].inset, 0.5);
+        assert!(map.tileset_mut("nope").is_none());
+    }
+
+    #[test]
+    fn group_named_like_its_child() {
+        let result = load(
+            r#"[{"name":"a","type":"group","layers":[
+                {"name":"a","type":"tilelayer","width":2,"height":1,"data":[1,2]}]}]"#,
+        );
+        assert!(matches!(result, Err(Error::NonUniqueLayerName { layer }) if layer == "a"));
+    }
+
+    #[test]
+    fn short_layer_data() {
+        let result = load(r#"[{"name":"a","type":"tilelayer","width":2,"height":1,"data":[1]}]"#);
+        assert!(matches!(
+            result,
+            Err(Error::LayerDataLength {
+                expected: 2,
+                len: 1,
+                ..
+            })
+        ));
+    }
 }
@@ -0,0 +1,375 @@
+use macroquad::prelude::*;
+
+use crate::properties::{color_to_string, parse_color, Properties};
+use crate::tiled;
+
+/// Geometry of a tiled object.
+/// Points of polygons and polylines are relative to the object position.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ObjectShape {
+    Rect,
+    Ellipse,
+    Point,
+    Polygon(Vec<Vec2>),
+    Polyline(Vec<Vec2>),
+    Text(Text),
+}
+
+/// Content and style of a text object
+#[derive(Debug, Clone, PartialEq)]
+pub struct Text {
+    pub text: String,
+    pub font_family: String,
+    pub pixel_size: u32,
+    pub color: Color,
+    pub wrap: bool,
+    pub bold: bool,
+    pub italic: bool,
+    /// "left", "center", "right" or "justify"
+    pub halign: String,
+    /// "top", "center" or "bottom"
+    pub valign: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct Object {
+    /// Unique id of the object in the map
+    pub id: u32,
+
+    /// If not null - the object is (probably) a tile
+    pub gid: Option<u32>,
+
+    pub world_x: f32,
+    pub world_y: f32,
+    pub world_w: f32,
+    pub world_h: f32,
+
+    pub tile_x: u32,
+    pub tile_y: u32,
+    pub tile_w: u32,
+    pub tile_h: u32,
+
+    pub name: String,
+    /// "type" from tiled, called "class" since tiled 1.9
+    pub ty: String,
+
+    pub shape: ObjectShape,
+    /// Clockwise rotation around the object position, in degrees
+    pub rotation: f32,
+    pub visible: bool,
+    /// Template file the object was instantiated from
+    pub template: Option<String>,
+
+    pub properties: Properties,
+}
+
+impl Object {
+    pub(crate) fn from_raw(
+        object: &tiled::layer::Object,
+        tile_width: f32,
+        tile_height: f32,
+    ) -> Object {
+        let points = |points: &Vec<tiled::layer::PolyPoint>| {
+            points
+                .iter()
+                .map(|point| vec2(point.x, point.y))
+                .collect::<Vec<_>>()
+        };
+
+        let shape = if let Some(text) = &object.text {
+            ObjectShape::Text(Text {
+                text: text.text.clone(),
+                font_family: if text.fontfamily.is_empty() {
+                    "sans-serif".to_owned()
+                } else {
+                    text.fontfamily.clone()
+                },
+                pixel_size: if text.pixelsize == 0 {
+                    16
+                } else {
+                    text.pixelsize
+                },
+                color: parse_color(&text.color).unwrap_or(BLACK),
+                wrap: text.wrap,
+                bold: text.bold,
+                italic: text.italic,
+                halign: if text.halign.is_empty() {
+                    "left".to_owned()
+                } else {
+                    text.halign.clone()
+                },
+                valign: if text.valign.is_empty() {
+                    "top".to_owned()
+                } else {
+                    text.valign.clone()
+                },
+            })
+        } else if let Some(polygon) = &object.polygon {
+            ObjectShape::Polygon(points(polygon))
+        } else if let Some(polyline) = &object.polyline {
+            ObjectShape::Polyline(points(polyline))
+        } else if object.ellipse == Some(true) {
+            ObjectShape::Ellipse
+        } else if object.point == Some(true) {
+            ObjectShape::Point
+        } else {
+            ObjectShape::Rect
+        };
+
+        Object {
+            id: object.id,
+            gid: object.gid,
+            world_x: object.x,
+            world_y: object.y,
+            world_w: object.width,
+            world_h: object.height,
+
+            tile_x: (object.x / tile_width) as u32,
+            tile_y: (object.y / tile_height) as u32,
+            tile_w: (object.width / tile_width) as u32,
+            tile_h: (object.height / tile_height) as u32,
+            name: object.name.clone(),
+            ty: if object.class.is_empty() {
+                object.ty.clone()
+            } else {
+                object.class.clone()
+            },
+            shape,
+            rotation: object.rotation,
+            visible: object.visible.unwrap_or(true),
+            template: object.template.clone(),
+            properties: Properties::from_raw(&object.properties),
+        }
+    }
+
+    /// Object as written in tiled files.
+    /// The class is written both as "type" and "class", for tiled versions
+    /// before and after 1.9.
+    pub(crate) fn to_raw(&self) -> tiled::layer::Object {
+        let points = |points: &[Vec2]| {
+            Some(
+                points
+                    .iter()
+                    .map(|point| tiled::layer::PolyPoint {
+                        x: point.x,
+                        y: point.y,
+                    })
+                    .collect(),
+            )
+        };
+
+        let mut object = tiled::layer::Object {
+            id: self.id,
+            name: self.name.clone(),
+            ty: self.ty.clone(),
+            class: self.ty.clone(),
+            gid: self.gid,
+            template: self.template.clone(),
+            properties: self.properties.to_raw(),
+            rotation: self.rotation,
+            visible: Some(self.visible),
+            width: self.world_w,
+            height: self.world_h,
+            x: self.world_x,
+            y: self.world_y,
+            ..Default::default()
+        };
+
+        match &self.shape {
+            ObjectShape::Rect => {}
+            ObjectShape::Ellipse => object.ellipse = Some(true),
+            ObjectShape::Point => object.point = Some(true),
+            ObjectShape::Polygon(polygon) => object.polygon = points(polygon),
+            ObjectShape::Polyline(polyline) => object.polyline = points(polyline),
+            ObjectShape::Text(text) => {
+                object.text = Some(tiled::layer::Text {
+                    text: text.text.clone(),
+                    fontfamily: text.font_family.clone(),
+                    pixelsize: text.pixel_size,
+                    color: color_to_string(text.color),
+                    wrap: text.wrap,
+                    bold: text.bold,
+                    italic: text.italic,
+                    halign: text.halign.clone(),
+                    valign: text.valign.clone(),
+                    ..Default::default()
+                })
+            }
+        }
+        object
+    }
+
+    /// Object position in world coordinates
+    pub fn position(&self) -> Vec2 {
+        vec2(self.world_x, self.world_y)
+    }
+
+    /// Object outline in world coordinates, with rotation applied.
+    /// Empty for points; rects, ellipses and texts produce their bounding box.
+    pub fn world_points(&self) -> Vec<Vec2> {
+        let local = match &self.shape {
+            ObjectShape::Polygon(points) | ObjectShape::Polyline(points) => points.clone(),
+            ObjectShape::Point => vec![],
+            ObjectShape::Rect | ObjectShape::Ellipse | ObjectShape::Text(_) => vec![
+                vec2(0., 0.),
+                vec2(self.world_w, 0.),
+                vec2(self.world_w, self.world_h),
+                vec2(0., self.world_h),
+            ],
+        };
+        let rotation = Mat2::from_angle(self.rotation.to_radians());
+
+        local
+            .into_iter()
+            .map(|point| self.position() + rotation * point)
+            .collect()
+    }
+
+    /// Check if world point "pos" is inside the object area.
+    /// Points and polylines have no area and never contain anything.
+    pub fn contains(&self, pos: Vec2) -> bool {
+        let local = Mat2::from_angle(-self.rotation.to_radians()) * (pos - self.position());
+
+        match &self.shape {
+            ObjectShape::Rect | ObjectShape::Text(_) => {
+                Rect::new(0., 0., self.world_w, self.world_h).contains(local)
+            }
+            ObjectShape::Ellipse => {
+                let radius = vec2(self.world_w, self.world_h) / 2.;
+                if radius.x <= 0. || radius.y <= 0. {
+                    return false;
+                }
+                let offset = (local - radius) / radius;
+                offset.dot(offset) <= 1.
+            }
+            ObjectShape::Polygon(points) => {
+                let mut inside = false;
+                let mut j = points.len().wrapping_sub(1);
+                for i in 0..points.len() {
+                    let (a, b) = (points[i], points[j]);
+                    if (a.y > local.y) != (b.y > local.y)
+                        && local.x < (b.x - a.x) * (local.y - a.y) / (b.y - a.y) + a.x
+                    {
+                        inside = !inside;
+                    }
+                    j = i;
+                }
+                inside
+            }
+            ObjectShape::Point | ObjectShape::Polyline(_) => false,
+        }
+    }
+}
+
+/// Instance fields override the template object, except the ones left at their default.
+/// "map_tilesets" are the raw map tilesets, used to remap the template tile gid.
+pub(crate) fn apply_template(
+    instance: &tiled::layer::Object,
+    template: &tiled::Template,
+    map_tilesets: &[tiled::Tileset],
+) -> tiled::layer::Object {
+    let mut object = template.object.clone();
+
+    object.id = instance.id;
+    object.x = instance.x;
+    object.y = instance.y;
+    object.template = instance.template.clone();
+
+    if instance.visible.is_some() {
+        object.visible = instance.visible;
+    }
+    if !instance.name.is_empty() {
+        object.name = instance.name.clone();
+    }
+    if !instance.ty.is_empty() {
+        object.ty = instance.ty.clone();
+    }
+    if !instance.class.is_empty() {
+        object.class = instance.class.clone();
+    }
+    if instance.width != 0. || instance.height != 0. {
+        object.width = instance.width;
+        object.height = instance.height;
+    }
+    if instance.rotation != 0. {
+        object.rotation = instance.rotation;
+    }
+    if instance.text.is_some() {
+        object.text = instance.text.clone();
+    }
+    if instance.polygon.is_some() {
+        object.polygon = instance.polygon.clone();
+    }
+    if instance.polyline.is_some() {
+        object.polyline = instance.polyline.clone();
+    }
+
+    for property in &instance.properties {
+        object.properties.retain(|p| p.name != property.name);
+        object.properties.push(property.clone());
+    }
+
+    if let Some(gid) = instance.gid {
+        object.gid = Some(gid);
+    } else if let (Some(gid), Some(tileset)) = (object.gid, &template.tileset) {
+        let file_name = |path: &Option<String>| {
+            path.as_deref()
+                .unwrap_or("")
+                .rsplit(&['/', '\\'][..])
+                .next()
+                .unwrap_or("")
+                .to_owned()
+        };
+
+        object.gid = map_tilesets
+            .iter()
+            .find(|map_tileset| file_name(&map_tileset.source) == file_name(&tileset.source))
+            .map(|map_tileset| map_tileset.firstgid + gid - tileset.firstgid);
+    }
+
+    object
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn template(visible: Option<bool>) -> tiled::Template {
+        tiled::Template {
+            tileset: None,
+            object: tiled::layer::Object {
+                name: "rock".to_owned(),
+                visible,
+                ..Default::default()
+            },
+        }
+    }
+
+    #[test]
+    fn template_visibility() {
+        let hidden = tiled::layer::Object {
+            visible: Some(false),
+            ..Default::default()
+        };
+        let shown = tiled::layer::Object {
+            visible: Some(true),
+            ..Default::default()
+        };
+        let unset = tiled::layer::Object::default();
+
+        assert_eq!(
+            apply_template(&hidden, &template(Some(true)), &[]).visible,
+            Some(false)
+        );
+        assert_eq!(
+            apply_template(&shown, &template(Some(false)), &[]).visible,
+            Some(true)
+        );
+        assert_eq!(
+            apply_template(&unset, &template(Some(false)), &[]).visible,
+            Some(false)
+        );
+        assert_eq!(apply_template(&unset, &template(None), &[]).visible, None);
+        assert_eq!(apply_template(&unset, &template(None), &[]).name, "rock");
+    }
+}
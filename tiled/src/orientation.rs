@@ -0,0 +1,489 @@
+use macroquad::prelude::*;
+
+use crate::tiled;
+
+/// Axis shifted every other row or column of staggered and hexagonal maps
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StaggerAxis {
+    X,
+    Y,
+}
+
+/// Whether the odd or the even rows/columns are shifted
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StaggerIndex {
+    Odd,
+    Even,
+}
+
+/// https://doc.mapeditor.org/en/stable/manual/projects/#map-orientation
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Orientation {
+    Orthogonal,
+    Isometric,
+    /// Isometric tiles in a staggered grid
+    Staggered {
+        axis: StaggerAxis,
+        index: StaggerIndex,
+    },
+    Hexagonal {
+        axis: StaggerAxis,
+        index: StaggerIndex,
+        /// Length of the flat side of the hexagons, in pixels
+        side_length: i32,
+    },
+}
+
+/// Order in which the tiles of orthogonal maps are drawn
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RenderOrder {
+    RightDown,
+    RightUp,
+    LeftDown,
+    LeftUp,
+}
+
+impl RenderOrder {
+    pub(crate) fn from_raw(render_order: &str) -> RenderOrder {
+        match render_order {
+            "right-up" => RenderOrder::RightUp,
+            "left-down" => RenderOrder::LeftDown,
+            "left-up" => RenderOrder::LeftUp,
+            _ => RenderOrder::RightDown,
+        }
+    }
+}
+
+/// Grid measures used by staggered and hexagonal maps, as in tiled's HexagonalRenderer
+struct Hex {
+    stagger_x: bool,
+    stagger_even: bool,
+    tile_width: f32,
+    tile_height: f32,
+    side_length_x: f32,
+    side_length_y: f32,
+    side_offset_x: f32,
+    side_offset_y: f32,
+    column_width: f32,
+    row_height: f32,
+}
+
+impl Hex {
+    fn new(axis: StaggerAxis, index: StaggerIndex, side_length: i32, tile_size: Vec2) -> Hex {
+        // tiled rounds the tile size down to even values
+        let tile_width = (tile_size.x as i32 & !1) as f32;
+        let tile_height = (tile_size.y as i32 & !1) as f32;
+        let stagger_x = axis == StaggerAxis::X;
+        let side_length_x = if stagger_x { side_length as f32 } else { 0. };
+        let side_length_y = if stagger_x { 0. } else { side_length as f32 };
+        let side_offset_x = ((tile_width - side_length_x) / 2.).floor();
+        let side_offset_y = ((tile_height - side_length_y) / 2.).floor();
+
+        Hex {
+            stagger_x,
+            stagger_even: index == StaggerIndex::Even,
+            tile_width,
+            tile_height,
+            side_length_x,
+            side_length_y,
+            side_offset_x,
+            side_offset_y,
+            column_width: side_offset_x + side_length_x,
+            row_height: side_offset_y + side_length_y,
+        }
+    }
+
+    fn is_staggered(&self, ix: i32) -> bool {
+        ((ix & 1) == 1) != self.stagger_even
+    }
+
+    fn tile_to_world(&self, x: i32, y: i32) -> Vec2 {
+        if self.stagger_x {
+            let mut pos = vec2(
+                x as f32 * self.column_width,
+                y as f32 * (self.tile_height + self.side_length_y),
+            );
+            if self.is_staggered(x) {
+                pos.y += self.row_height;
+            }
+            pos
+        } else {
+            let mut pos = vec2(
+                x as f32 * (self.tile_width + self.side_length_x),
+                y as f32 * self.row_height,
+            );
+            if self.is_staggered(y) {
+                pos.x += self.column_width;
+            }
+            pos
+        }
+    }
+
+    /// Tile with the closest center
+    fn world_to_tile(&self, mut pos: Vec2) -> (i32, i32) {
+        if self.stagger_x {
+            pos.x -= if self.stagger_even {
+                self.tile_width
+            } else {
+                self.side_offset_x
+            };
+        } else {
+            pos.y -= if self.stagger_even {
+                self.tile_height
+            } else {
+                self.side_offset_y
+            };
+        }
+
+        let mut reference = (
+            (pos.x / (self.column_width * 2.)).floor() as i32,
+            (pos.y / (self.row_height * 2.)).floor() as i32,
+        );
+        let relative = vec2(
+            pos.x - reference.0 as f32 * self.column_width * 2.,
+            pos.y - reference.1 as f32 * self.row_height * 2.,
+        );
+
+        let (centers, offsets) = if self.stagger_x {
+            reference.0 = reference.0 * 2 + self.stagger_even as i32;
+            let left = self.side_length_x / 2.;
+            let center_x = left + self.column_width;
+            let center_y = self.tile_height / 2.;
+            (
+                [
+                    vec2(left, center_y),
+                    vec2(center_x, center_y - self.row_height),
+                    vec2(center_x, center_y + self.row_height),
+                    vec2(center_x + self.column_width, center_y),
+                ],
+                [(0, 0), (1, -1), (1, 0), (2, 0)],
+            )
+        } else {
+            reference.1 = reference.1 * 2 + self.stagger_even as i32;
+            let top = self.side_length_y / 2.;
+            let center_x = self.tile_width / 2.;
+            let center_y = top + self.row_height;
+            (
+                [
+                    vec2(center_x, top),
+                    vec2(center_x - self.column_width, center_y),
+                    vec2(center_x + self.column_width, center_y),
+                    vec2(center_x, center_y + self.row_height),
+                ],
+                [(0, 0), (-1, 1), (0, 1), (0, 2)],
+            )
+        };
+
+        let nearest = (0..4)
+            .min_by(|a, b| {
+                let a = (centers[*a] - relative).length_squared();
+                let b = (centers[*b] - relative).length_squared();
+                a.partial_cmp(&b).unwrap_or(std::cmp::Ordering::Equal)
+            })
+            .unwrap_or(0);
+
+        (
+            reference.0 + offsets[nearest].0,
+            reference.1 + offsets[nearest].1,
+        )
+    }
+
+    fn pixel_size(&self, width: u32, height: u32) -> Vec2 {
+        if self.stagger_x {
+            let mut size = vec2(
+                width as f32 * self.column_width + self.side_offset_x,
+                height as f32 * (self.tile_height + self.side_length_y),
+            );
+            if width > 1 {
+                size.y += self.row_height;
+            }
+            size
+        } else {
+            let mut size = vec2(
+                width as f32 * (self.tile_width + self.side_length_x),
+                height as f32 * self.row_height + self.side_offset_y,
+            );
+            if height > 1 {
+                size.x += self.column_width;
+            }
+            size
+        }
+    }
+}
+
+impl Orientation {
+    pub(crate) fn from_raw(map: &tiled::Map) -> Orientation {
+        let axis = match map.staggeraxis.as_deref() {
+            Some("x") => StaggerAxis::X,
+            _ => StaggerAxis::Y,
+        };
+        let index = match map.staggerindex.as_deref() {
+            Some("even") => StaggerIndex::Even,
+            _ => StaggerIndex::Odd,
+        };
+
+        match map.orientation.as_str() {
+            "isometric" => Orientation::Isometric,
+            "staggered" => Orientation::Staggered { axis, index },
+            "hexagonal" => Orientation::Hexagonal {
+                axis,
+                index,
+                side_length: map.hexsidelength.unwrap_or(0),
+            },
+            _ => Orientation::Orthogonal,
+        }
+    }
+
+    fn hex(&self, tile_size: Vec2) -> Option<Hex> {
+        match *self {
+            Orientation::Staggered { axis, index } => Some(Hex::new(axis, index, 0, tile_size)),
+            Orientation::Hexagonal {
+                axis,
+                index,
+                side_length,
+            } => Some(Hex::new(axis, index, side_length, tile_size)),
+            _ => None,
+        }
+    }
+
+    /// Top left corner of the bounding box of tile "x", "y", in map pixels.
+    /// "map_size" is the map size in tiles.
+    pub(crate) fn tile_to_world(
+        &self,
+        x: i32,
+        y: i32,
+        tile_size: Vec2,
+        map_size: (u32, u32),
+    ) -> Vec2 {
+        match self {
+            Orientation::Orthogonal => vec2(x as f32, y as f32) * tile_size,
+            Orientation::Isometric => {
+                let origin_x = map_size.1 as f32 * tile_size.x / 2.;
+                vec2(
+                    (x - y) as f32 * tile_size.x / 2. + origin_x - tile_size.x / 2.,
+                    (x + y) as f32 * tile_size.y / 2.,
+                )
+            }
+            _ => self.hex(tile_size).unwrap().tile_to_world(x, y),
+        }
+    }
+
+    /// Tile containing the map pixel "pos", may be outside of the map
+    pub(crate) fn world_to_tile(
+        &self,
+        pos: Vec2,
+        tile_size: Vec2,
+        map_size: (u32, u32),
+    ) -> (i32, i32) {
+        match self {
+            Orientation::Orthogonal => {
+                let tile = (pos / tile_size).floor();
+                (tile.x as i32, tile.y as i32)
+            }
+            Orientation::Isometric => {
+                let x = (pos.x - map_size.1 as f32 * tile_size.x / 2.) / tile_size.x;
+                let y = pos.y / tile_size.y;
+                ((y + x).floor() as i32, (y - x).floor() as i32)
+            }
+            Orientation::Staggered { .. } => {
+                // the closest center is only an estimate for diamonds,
+                // pick the neighbour whose diamond actually contains "pos"
+                let (x, y) = self.hex(tile_size).unwrap().world_to_tile(pos);
+                let distance = |(x, y): (i32, i32)| {
+                    let center = self.tile_to_world(x, y, tile_size, map_size) + tile_size / 2.;
+                    let offset = (pos - center).abs() / (tile_size / 2.);
+                    offset.x + offset.y
+                };
+
+                (-1..=1)
+                    .flat_map(|dy| (-1..=1).map(move |dx| (x + dx, y + dy)))
+                    .min_by(|a, b| {
+                        distance(*a)
+                            .partial_cmp(&distance(*b))
+                            .unwrap_or(std::cmp::Ordering::Equal)
+                    })
+                    .unwrap_or((x, y))
+            }
+            Orientation::Hexagonal { .. } => self.hex(tile_size).unwrap().world_to_tile(pos),
+        }
+    }
+
+    /// Size of the whole map in pixels
+    pub(crate) fn pixel_size(&self, tile_size: Vec2, map_size: (u32, u32)) -> Vec2 {
+        match self {
+            Orientation::Orthogonal => vec2(map_size.0 as f32, map_size.1 as f32) * tile_size,
+            Orientation::Isometric => (map_size.0 + map_size.1) as f32 * tile_size / 2.,
+            _ => self
+                .hex(tile_size)
+                .unwrap()
+                .pixel_size(map_size.0, map_size.1),
+        }
+    }
+
+    /// Staggered X maps draw each row in two passes, the shifted columns
+    /// being in front of the others.
+    /// Returns whether column "x" is drawn in the second pass.
+    pub(crate) fn second_pass(&self, x: i32, tile_size: Vec2) -> Option<bool> {
+        match self.hex(tile_size) {
+            Some(hex) if hex.stagger_x => Some(hex.is_staggered(x)),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MAP_SIZE: (u32, u32) = (4, 3);
+
+    fn hexagonal(axis: StaggerAxis, index: StaggerIndex, side_length: i32) -> Orientation {
+        Orientation::Hexagonal {
+            axis,
+            index,
+            side_length,
+        }
+    }
+
+    #[test]
+    fn tile_centers_round_trip() {
+        let orientations = [
+            (Orientation::Orthogonal, vec2(32., 16.)),
+            (Orientation::Isometric, vec2(32., 16.)),
+            (
+                Orientation::Staggered {
+                    axis: StaggerAxis::Y,
+                    index: StaggerIndex::Odd,
+                },
+                vec2(32., 16.),
+            ),
+            (
+                Orientation::Staggered {
+                    axis: StaggerAxis::X,
+                    index: StaggerIndex::Even,
+                },
+                vec2(32., 16.),
+            ),
+            (
+                hexagonal(StaggerAxis::Y, StaggerIndex::Odd, 16),
+                vec2(32., 32.),
+            ),
+            (
+                hexagonal(StaggerAxis::Y, StaggerIndex::Even, 8),
+                vec2(32., 16.),
+            ),
+            (
+                hexagonal(StaggerAxis::X, StaggerIndex::Odd, 12),
+                vec2(32., 16.),
+            ),
+            (
+                hexagonal(StaggerAxis::X, StaggerIndex::Even, 16),
+                vec2(32., 32.),
+            ),
+        ];
+
+        for (orientation, tile_size) in &orientations {
+            for y in 0..MAP_SIZE.1 as i32 {
+                for x in 0..MAP_SIZE.0 as i32 {
+                    let center =
+                        orientation.tile_to_world(x, y, *tile_size, MAP_SIZE) + *tile_size / 2.;
+                    assert_eq!(
+                        orientation.world_to_tile(center, *tile_size, MAP_SIZE),
+                        (x, y),
+                        "{:?}",
+                        orientation
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn isometric() {
+        let tile_size = vec2(32., 16.);
+        let orientation = Orientation::Isometric;
+
+        // the top corner of tile (0, 0) is in the middle of the top of the map
+        assert_eq!(
+            orientation.tile_to_world(0, 0, tile_size, MAP_SIZE),
+            vec2(32., 0.)
+        );
+        assert_eq!(
+            orientation.tile_to_world(1, 0, tile_size, MAP_SIZE),
+            vec2(48., 8.)
+        );
+        assert_eq!(
+            orientation.tile_to_world(0, 1, tile_size, MAP_SIZE),
+            vec2(16., 8.)
+        );
+        assert_eq!(orientation.pixel_size(tile_size, MAP_SIZE), vec2(112., 56.));
+        // above the top left edge of the map
+        assert_eq!(
+            orientation.world_to_tile(vec2(40., 1.), tile_size, MAP_SIZE),
+            (-1, 0)
+        );
+    }
+
+    #[test]
+    fn hexagonal_pointy_top() {
+        let tile_size = vec2(32., 32.);
+        let orientation = hexagonal(StaggerAxis::Y, StaggerIndex::Odd, 16);
+
+        assert_eq!(
+            orientation.tile_to_world(1, 0, tile_size, MAP_SIZE),
+            vec2(32., 0.)
+        );
+        // odd rows are shifted by half a tile
+        assert_eq!(
+            orientation.tile_to_world(0, 1, tile_size, MAP_SIZE),
+            vec2(16., 24.)
+        );
+        assert_eq!(orientation.pixel_size(tile_size, MAP_SIZE), vec2(144., 80.));
+        assert_eq!(orientation.second_pass(1, tile_size), None);
+    }
+
+    #[test]
+    fn hexagonal_flat_top() {
+        let tile_size = vec2(32., 32.);
+        let orientation = hexagonal(StaggerAxis::X, StaggerIndex::Even, 16);
+
+        // even columns are shifted by half a tile
+        assert_eq!(
+            orientation.tile_to_world(0, 0, tile_size, MAP_SIZE),
+            vec2(0., 16.)
+        );
+        assert_eq!(
+            orientation.tile_to_world(1, 0, tile_size, MAP_SIZE),
+            vec2(24., 0.)
+        );
+        assert_eq!(
+            orientation.pixel_size(tile_size, MAP_SIZE),
+            vec2(104., 112.)
+        );
+        assert_eq!(orientation.second_pass(0, tile_size), Some(true));
+        assert_eq!(orientation.second_pass(1, tile_size), Some(false));
+    }
+
+    #[test]
+    fn orthogonal() {
+        let tile_size = vec2(32., 16.);
+        let orientation = Orientation::Orthogonal;
+
+        assert_eq!(
+            orientation.tile_to_world(2, 1, tile_size, MAP_SIZE),
+            vec2(64., 16.)
+        );
+        assert_eq!(
+            orientation.world_to_tile(vec2(-1., 16.), tile_size, MAP_SIZE),
+            (-1, 1)
+        );
+        assert_eq!(orientation.pixel_size(tile_size, MAP_SIZE), vec2(128., 48.));
+        assert_eq!(orientation.second_pass(1, tile_size), None);
+    }
+
+    #[test]
+    fn render_order() {
+        assert_eq!(RenderOrder::from_raw("left-up"), RenderOrder::LeftUp);
+        assert_eq!(RenderOrder::from_raw(""), RenderOrder::RightDown);
+    }
+}
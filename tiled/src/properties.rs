@@ -0,0 +1,324 @@
+use macroquad::color::Color;
+
+use std::collections::HashMap;
+
+use crate::tiled::{JsonValue, Property};
+
+/// Typed value of a tiled custom property
+/// https://doc.mapeditor.org/en/stable/manual/custom-properties/
+#[derive(Debug, Clone, PartialEq)]
+pub enum PropertyValue {
+    Bool(bool),
+    Int(i64),
+    Float(f64),
+    String(String),
+    Color(Color),
+    /// Path to a file, relative to the map
+    File(String),
+    /// Id of the referenced object, 0 when no object is referenced
+    Object(u32),
+    /// Custom class value, "property_type" is the class name
+    Class {
+        property_type: String,
+        properties: Properties,
+    },
+}
+
+impl PropertyValue {
+    fn from_raw(ty: &str, property_type: &str, value: &JsonValue) -> PropertyValue {
+        match (ty, value) {
+            ("bool", JsonValue::Bool(value)) => PropertyValue::Bool(*value),
+            ("int", JsonValue::Int(value)) => PropertyValue::Int(*value),
+            ("int", JsonValue::Float(value)) => PropertyValue::Int(*value as i64),
+            ("float", JsonValue::Int(value)) => PropertyValue::Float(*value as f64),
+            ("float", JsonValue::Float(value)) => PropertyValue::Float(*value),
+            ("color", JsonValue::String(value)) => {
+                PropertyValue::Color(parse_color(value).unwrap_or(Color::new(0., 0., 0., 0.)))
+            }
+            ("file", JsonValue::String(value)) => PropertyValue::File(value.clone()),
+            ("object", JsonValue::Int(value)) => PropertyValue::Object(*value as u32),
+            ("class", JsonValue::Object(fields)) => PropertyValue::Class {
+                property_type: property_type.to_owned(),
+                properties: Properties::from_json_fields(fields),
+            },
+            ("class", JsonValue::Null) => PropertyValue::Class {
+                property_type: property_type.to_owned(),
+                properties: Properties::default(),
+            },
+            _ => PropertyValue::from_json(value),
+        }
+    }
+
+    /// Tiled property "type", "propertytype" and value
+    fn to_raw(&self) -> (&'static str, String, JsonValue) {
+        match self {
+            PropertyValue::Bool(value) => ("bool", String::new(), JsonValue::Bool(*value)),
+            PropertyValue::Int(value) => ("int", String::new(), JsonValue::Int(*value)),
+            PropertyValue::Float(value) => ("float", String::new(), JsonValue::Float(*value)),
+            PropertyValue::String(value) => {
+                ("string", String::new(), JsonValue::String(value.clone()))
+            }
+            PropertyValue::Color(color) => (
+                "color",
+                String::new(),
+                JsonValue::String(color_to_string(*color)),
+            ),
+            PropertyValue::File(value) => ("file", String::new(), JsonValue::String(value.clone())),
+            PropertyValue::Object(value) => {
+                ("object", String::new(), JsonValue::Int(*value as i64))
+            }
+            PropertyValue::Class {
+                property_type,
+                properties,
+            } => ("class", property_type.clone(), properties.to_json_fields()),
+        }
+    }
+
+    /// Class members do not carry their type in the map file,
+    /// so the type is guessed from the json value.
+    fn from_json(value: &JsonValue) -> PropertyValue {
+        match value {
+            JsonValue::Bool(value) => PropertyValue::Bool(*value),
+            JsonValue::Int(value) => PropertyValue::Int(*value),
+            JsonValue::Float(value) => PropertyValue::Float(*value),
+            JsonValue::String(value) => PropertyValue::String(value.clone()),
+            JsonValue::Object(fields) => PropertyValue::Class {
+                property_type: String::new(),
+                properties: Properties::from_json_fields(fields),
+            },
+            JsonValue::Null | JsonValue::Array(_) => PropertyValue::String(String::new()),
+        }
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            PropertyValue::Bool(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    /// Integer value, object references are integers as well
+    pub fn as_int(&self) -> Option<i64> {
+        match self {
+            PropertyValue::Int(value) => Some(*value),
+            PropertyValue::Object(value) => Some(*value as i64),
+            _ => None,
+        }
+    }
+
+    /// Float value, integers are converted to floats
+    pub fn as_float(&self) -> Option<f64> {
+        match self {
+            PropertyValue::Float(value) => Some(*value),
+            PropertyValue::Int(value) => Some(*value as f64),
+            _ => None,
+        }
+    }
+
+    /// String value, file paths are strings as well
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            PropertyValue::String(value) | PropertyValue::File(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    pub fn as_color(&self) -> Option<Color> {
+        match self {
+            PropertyValue::Color(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    pub fn as_file(&self) -> Option<&str> {
+        match self {
+            PropertyValue::File(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    pub fn as_object(&self) -> Option<u32> {
+        match self {
+            PropertyValue::Object(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    pub fn as_class(&self) -> Option<&Properties> {
+        match self {
+            PropertyValue::Class { properties, .. } => Some(properties),
+            _ => None,
+        }
+    }
+}
+
+impl std::fmt::Display for PropertyValue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PropertyValue::Bool(value) => write!(f, "{}", value),
+            PropertyValue::Int(value) => write!(f, "{}", value),
+            PropertyValue::Float(value) => write!(f, "{}", value),
+            PropertyValue::String(value) | PropertyValue::File(value) => write!(f, "{}", value),
+            PropertyValue::Color(color) => write!(f, "{}", color_to_string(*color)),
+            PropertyValue::Object(value) => write!(f, "{}", value),
+            PropertyValue::Class { property_type, .. } => write!(f, "{}", property_type),
+        }
+    }
+}
+
+/// Parse tiled "#RRGGBB" or "#AARRGGBB" color
+pub(crate) fn parse_color(color: &str) -> Option<Color> {
+    let hex = color.strip_prefix('#').unwrap_or(color);
+    let channel = |ix: usize| {
+        hex.get(ix..ix + 2)
+            .and_then(|channel| u8::from_str_radix(channel, 16).ok())
+            .map(|channel| channel as f32 / 255.)
+    };
+
+    match hex.len() {
+        6 => Some(Color::new(channel(0)?, channel(2)?, channel(4)?, 1.)),
+        8 => Some(Color::new(
+            channel(2)?,
+            channel(4)?,
+            channel(6)?,
+            channel(0)?,
+        )),
+        _ => None,
+    }
+}
+
+/// Format a color the way tiled does, "#AARRGGBB"
+pub(crate) fn color_to_string(color: Color) -> String {
+    format!(
+        "#{:02x}{:02x}{:02x}{:02x}",
+        (color.a * 255.) as u8,
+        (color.r * 255.) as u8,
+        (color.g * 255.) as u8,
+        (color.b * 255.) as u8
+    )
+}
+
+/// Custom properties of a map, layer, tile or object
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Properties {
+    values: HashMap<String, PropertyValue>,
+}
+
+impl Properties {
+    pub(crate) fn from_raw(properties: &[Property]) -> Properties {
+        Properties {
+            values: properties
+                .iter()
+                .map(|property| {
+                    (
+                        property.name.clone(),
+                        PropertyValue::from_raw(
+                            &property.ty,
+                            &property.propertytype,
+                            &property.value,
+                        ),
+                    )
+                })
+                .collect(),
+        }
+    }
+
+    fn from_json_fields(fields: &[(String, JsonValue)]) -> Properties {
+        Properties {
+            values: fields
+                .iter()
+                .map(|(name, value)| (name.clone(), PropertyValue::from_json(value)))
+                .collect(),
+        }
+    }
+
+    /// Properties as written in tiled files, sorted by name
+    pub(crate) fn to_raw(&self) -> Vec<Property> {
+        let mut properties = self
+            .values
+            .iter()
+            .map(|(name, value)| {
+                let (ty, propertytype, value) = value.to_raw();
+                Property {
+                    name: name.clone(),
+                    ty: ty.to_owned(),
+                    propertytype,
+                    value,
+                }
+            })
+            .collect::<Vec<_>>();
+        properties.sort_by(|a, b| a.name.cmp(&b.name));
+        properties
+    }
+
+    fn to_json_fields(&self) -> JsonValue {
+        JsonValue::Object(
+            self.to_raw()
+                .into_iter()
+                .map(|property| (property.name, property.value))
+                .collect(),
+        )
+    }
+
+    pub fn get(&self, name: &str) -> Option<&PropertyValue> {
+        self.values.get(name)
+    }
+
+    pub fn get_bool(&self, name: &str) -> Option<bool> {
+        self.get(name).and_then(PropertyValue::as_bool)
+    }
+
+    pub fn get_int(&self, name: &str) -> Option<i64> {
+        self.get(name).and_then(PropertyValue::as_int)
+    }
+
+    pub fn get_float(&self, name: &str) -> Option<f64> {
+        self.get(name).and_then(PropertyValue::as_float)
+    }
+
+    pub fn get_string(&self, name: &str) -> Option<&str> {
+        self.get(name).and_then(PropertyValue::as_str)
+    }
+
+    pub fn get_color(&self, name: &str) -> Option<Color> {
+        self.get(name).and_then(PropertyValue::as_color)
+    }
+
+    pub fn get_file(&self, name: &str) -> Option<&str> {
+        self.get(name).and_then(PropertyValue::as_file)
+    }
+
+    pub fn get_object(&self, name: &str) -> Option<u32> {
+        self.get(name).and_then(PropertyValue::as_object)
+    }
+
+    pub fn get_class(&self, name: &str) -> Option<&Properties> {
+        self.get(name).and_then(PropertyValue::as_class)
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.values.contains_key(name)
+    }
+
+    pub fn insert(&mut self, name: &str, value: PropertyValue) -> Option<PropertyValue> {
+        self.values.insert(name.to_owned(), value)
+    }
+
+    pub fn remove(&mut self, name: &str) -> Option<PropertyValue> {
+        self.values.remove(name)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &PropertyValue)> {
+        self.values
+            .iter()
+            .map(|(name, value)| (name.as_str(), value))
+    }
+
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+}
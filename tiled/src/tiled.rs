@@ -1,35 +1,116 @@
-use nanoserde::DeJson;
+use nanoserde::{DeJson, DeJsonErr, DeJsonState, DeJsonTok, SerJson, SerJsonState};
+
+use std::str::Chars;
 
 pub mod layer;
 
 /// https://doc.mapeditor.org/en/stable/reference/tmx-map-format/#tmx-grid
-#[derive(Clone, Debug, Default, DeJson)]
+#[derive(Clone, Debug, Default, DeJson, SerJson)]
 pub struct Grid {
     pub width: i32,
     pub height: i32,
 }
 
-#[derive(Clone, Debug, Default, DeJson)]
+/// Any json value.
+/// Property values are stored this way, their actual type is only known
+/// from the "type" field of the property.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub enum JsonValue {
+    #[default]
+    Null,
+    Bool(bool),
+    Int(i64),
+    Float(f64),
+    String(String),
+    Array(Vec<JsonValue>),
+    Object(Vec<(String, JsonValue)>),
+}
+
+impl DeJson for JsonValue {
+    fn de_json(s: &mut DeJsonState, i: &mut Chars) -> Result<JsonValue, DeJsonErr> {
+        let value = match s.tok {
+            DeJsonTok::Null => JsonValue::Null,
+            DeJsonTok::Bool(value) => JsonValue::Bool(value),
+            DeJsonTok::U64(value) => JsonValue::Int(value as i64),
+            DeJsonTok::I64(value) => JsonValue::Int(value),
+            DeJsonTok::F64(value) => JsonValue::Float(value),
+            DeJsonTok::Str => JsonValue::String(s.as_string()?),
+            DeJsonTok::BlockOpen => {
+                s.block_open(i)?;
+                let mut values = vec![];
+                while s.tok != DeJsonTok::BlockClose {
+                    values.push(JsonValue::de_json(s, i)?);
+                    s.eat_comma_block(i)?;
+                }
+                s.block_close(i)?;
+                return Ok(JsonValue::Array(values));
+            }
+            DeJsonTok::CurlyOpen => {
+                s.curly_open(i)?;
+                let mut fields = vec![];
+                while s.next_str().is_some() {
+                    let key = s.as_string()?;
+                    s.next_colon(i)?;
+                    fields.push((key, JsonValue::de_json(s, i)?));
+                    s.eat_comma_curly(i)?;
+                }
+                s.curly_close(i)?;
+                return Ok(JsonValue::Object(fields));
+            }
+            _ => return Err(s.err_token("json value")),
+        };
+        s.next_tok(i)?;
+        Ok(value)
+    }
+}
+
+impl SerJson for JsonValue {
+    fn ser_json(&self, d: usize, s: &mut SerJsonState) {
+        match self {
+            JsonValue::Null => s.out.push_str("null"),
+            JsonValue::Bool(value) => value.ser_json(d, s),
+            JsonValue::Int(value) => value.ser_json(d, s),
+            JsonValue::Float(value) => value.ser_json(d, s),
+            JsonValue::String(value) => value.ser_json(d, s),
+            JsonValue::Array(values) => values.ser_json(d, s),
+            JsonValue::Object(fields) => {
+                s.st_pre();
+                for (ix, (key, value)) in fields.iter().enumerate() {
+                    if ix != 0 {
+                        s.conl();
+                    }
+                    s.field(d + 1, key);
+                    value.ser_json(d + 1, s);
+                }
+                s.st_post(d);
+            }
+        }
+    }
+}
+
+/// https://doc.mapeditor.org/en/stable/reference/json-map-format/#property
+#[derive(Clone, Debug, Default, DeJson, SerJson)]
+#[nserde(default)]
 pub struct Property {
     pub name: String,
-    pub value: String,
+    /// Type of the property: string, int, float, bool, color, file, object or class
     #[nserde(rename = "type")]
     pub ty: String,
+    /// Name of the custom property type (optional)
+    pub propertytype: String,
+    /// Value of the property, its json type depends on "type"
+    pub value: JsonValue,
 }
 
-/// https://doc.mapeditor.org/en/stable/reference/json-map-format/#json-layer
-#[derive(Clone, Debug, Default, DeJson)]
-pub struct Layer {}
-
 /// https://doc.mapeditor.org/en/stable/reference/json-map-format/#json-frame
-#[derive(Clone, Debug, Default, DeJson)]
+#[derive(Clone, Debug, Default, DeJson, SerJson)]
 pub struct Frame {
     pub duration: i32,
     pub tileid: i32,
 }
 
 /// https://doc.mapeditor.org/en/stable/reference/json-map-format/#json-tile
-#[derive(Clone, Debug, Default, DeJson)]
+#[derive(Clone, Debug, Default, DeJson, SerJson)]
 #[nserde(default)]
 pub struct Tile {
     /// Array of Frames
@@ -43,7 +124,7 @@ pub struct Tile {
     /// Width of the tile image in pixels
     pub imageheight: i32,
     /// Layer with type objectgroup (optional)
-    pub objectgroup: Option<Layer>,
+    pub objectgroup: Option<layer::Layer>,
     /// A list of properties (name, value, type)
     pub properties: Vec<Property>,
     /// Index of terrain for each corner of tile
@@ -54,21 +135,62 @@ pub struct Tile {
 }
 
 /// https://doc.mapeditor.org/en/stable/reference/tmx-map-format/#tmx-tileoffset
-#[derive(Clone, Debug, Default, DeJson)]
+#[derive(Clone, Debug, Default, DeJson, SerJson)]
 pub struct Tileoffset {
     pub x: i32,
     pub y: i32,
 }
 
 /// https://doc.mapeditor.org/en/stable/reference/json-map-format/#json-terrain
-#[derive(Clone, Debug, Default, DeJson)]
+#[derive(Clone, Debug, Default, DeJson, SerJson)]
 pub struct Terrain {
     pub name: String,
     pub tile: i32,
 }
 
+/// https://doc.mapeditor.org/en/stable/reference/json-map-format/#wang-color
+#[derive(Clone, Debug, Default, DeJson, SerJson)]
+#[nserde(default)]
+pub struct WangColor {
+    /// Hex-formatted color (#RRGGBB or #AARRGGBB)
+    pub color: String,
+    pub name: String,
+    /// Probability used when randomizing
+    #[nserde(default = "1.0")]
+    pub probability: f32,
+    pub properties: Vec<Property>,
+    /// Local ID of tile representing the Wang color
+    pub tile: i32,
+}
+
+/// https://doc.mapeditor.org/en/stable/reference/json-map-format/#wang-tile
+#[derive(Clone, Debug, Default, DeJson, SerJson)]
+#[nserde(default)]
+pub struct WangTile {
+    /// Local ID of tile
+    pub tileid: u32,
+    /// Array of Wang color indexes (uchar[8])
+    pub wangid: Vec<u32>,
+}
+
+/// https://doc.mapeditor.org/en/stable/reference/json-map-format/#wang-set
+#[derive(Clone, Debug, Default, DeJson, SerJson)]
+#[nserde(default)]
+pub struct WangSet {
+    /// Array of Wang colors (since 1.5)
+    pub colors: Vec<WangColor>,
+    pub name: String,
+    pub properties: Vec<Property>,
+    /// Local ID of tile representing the Wang set
+    pub tile: i32,
+    /// corner, edge or mixed (since 1.5)
+    #[nserde(rename = "type")]
+    pub ty: String,
+    pub wangtiles: Vec<WangTile>,
+}
+
 /// https://doc.mapeditor.org/en/stable/reference/json-map-format/#tileset
-#[derive(Clone, Debug, Default, DeJson)]
+#[derive(Clone, Debug, Default, DeJson, SerJson)]
 #[nserde(default)]
 pub struct Tileset {
     /// The number of tile columns in the tileset
@@ -106,12 +228,14 @@ pub struct Tileset {
     pub tilewidth: i32,
     /// Hex-formatted color (#RRGGBB) (optional)
     pub transparentcolor: Option<String>,
-    /// The external file that contains this tilesets data
-    pub source: String,
+    /// Array of Wang sets (since 1.1.5)
+    pub wangsets: Vec<WangSet>,
+    /// The external file that contains this tilesets data (optional)
+    pub source: Option<String>,
 }
 
 /// https://doc.mapeditor.org/en/stable/reference/json-map-format/#map
-#[derive(Clone, Debug, Default, DeJson)]
+#[derive(Clone, Debug, Default, DeJson, SerJson)]
 #[nserde(default)]
 pub struct Map {
     /// Hex-formatted color (#RRGGBB or #AARRGGBB) (optional)
@@ -121,8 +245,16 @@ pub struct Map {
 
     pub properties: Vec<Property>,
 
+    /// orthogonal, isometric, staggered or hexagonal
     pub orientation: String,
+    /// right-down (the default), right-up, left-down or left-up (currently only supported for orthogonal maps)
     pub renderorder: String,
+    /// x or y (staggered / hexagonal maps only)
+    pub staggeraxis: Option<String>,
+    /// odd or even (staggered / hexagonal maps only)
+    pub staggerindex: Option<String>,
+    /// Length of the side of a hex tile in pixels (hexagonal maps only)
+    pub hexsidelength: Option<i32>,
 
     pub tileheight: u32,
     pub tilewidth: u32,
@@ -135,6 +267,55 @@ pub struct Map {
     /// Number of tile columns
     pub width: u32,
 
+    /// Whether the map has infinite dimensions
+    pub infinite: bool,
+    /// Auto-increments for each layer
+    pub nextlayerid: u32,
+    /// Auto-increments for each placed object
+    pub nextobjectid: u32,
+
     #[nserde(rename = "type")]
     pub ty: String,
 }
+
+/// https://doc.mapeditor.org/en/stable/manual/worlds/
+#[derive(Clone, Debug, Default, DeJson)]
+#[nserde(default)]
+pub struct World {
+    pub maps: Vec<WorldMap>,
+    /// Maps placed by matching their file names (optional)
+    pub patterns: Vec<WorldPattern>,
+    #[nserde(rename = "onlyShowAdjacentMaps")]
+    pub only_show_adjacent_maps: bool,
+    #[nserde(rename = "type")]
+    pub ty: String,
+}
+
+#[derive(Clone, Debug, Default, DeJson)]
+#[nserde(default)]
+pub struct WorldMap {
+    /// Map file, relative to the world file
+    #[nserde(rename = "fileName")]
+    pub file_name: String,
+    /// Position of the map in the world, in pixels
+    pub x: i32,
+    pub y: i32,
+    pub width: i32,
+    pub height: i32,
+}
+
+#[derive(Clone, Debug, Default, DeJson)]
+#[nserde(default)]
+pub struct WorldPattern {
+    pub regexp: String,
+}
+
+/// https://doc.mapeditor.org/en/stable/reference/json-map-format/#object-template
+#[derive(Clone, Debug, Default, DeJson, SerJson)]
+#[nserde(default)]
+pub struct Template {
+    /// External tileset used by the template (optional)
+    pub tileset: Option<Tileset>,
+    /// The object instantiated by this template
+    pub object: layer::Object,
+}
@@ -1,9 +1,9 @@
-use nanoserde::DeJson;
+use nanoserde::{DeJson, SerJson};
 
-use std::collections::HashMap;
+use super::Property;
 
 /// https://doc.mapeditor.org/en/stable/reference/json-map-format/#json-chunk
-#[derive(Clone, Debug, Default, DeJson)]
+#[derive(Clone, Debug, Default, DeJson, SerJson)]
 #[nserde(default)]
 pub struct Chunk {
     /// Array of unsigned int (GIDs) or base64-encoded data
@@ -18,14 +18,19 @@ pub struct Chunk {
     pub y: i32,
 }
 
-#[derive(Clone, Debug, Default, DeJson)]
+#[derive(Clone, Debug, Default, DeJson, SerJson)]
 #[nserde(default)]
 pub struct Layer {
     /// Array of chunks (optional). tilelayer only.
     pub chunks: Option<Vec<Chunk>>,
+    /// Incremental ID - unique across all layers
+    pub id: u32,
     pub name: String,
+    /// Value between 0 and 1
+    #[nserde(default = "1.0")]
     pub opacity: f32,
-    pub properties: Option<HashMap<String, String>>,
+    pub properties: Vec<Property>,
+    #[nserde(default = "true")]
     pub visible: bool,
     pub width: u32,
     pub height: u32,
@@ -47,18 +52,26 @@ pub struct Layer {
     pub x: Option<f32>,
     /// Vertical layer offset in tiles. Always 0.
     pub y: Option<f32>,
-}
+    /// Horizontal parallax factor for this layer (default: 1)
+    pub parallaxx: Option<f32>,
+    /// Vertical parallax factor for this layer (default: 1)
+    pub parallaxy: Option<f32>,
+    /// Hex-formatted tint color (#RRGGBB or #AARRGGBB) that is multiplied with any graphics drawn by this layer or any child layers (optional)
+    pub tintcolor: Option<String>,
 
-#[derive(Clone, Debug, Default, DeJson)]
-#[nserde(default)]
-pub struct Property {
-    pub name: String,
-    #[nserde(rename = "type")]
-    pub ty: String,
-    pub value: String,
+    /// for type = "group"
+    #[nserde(default)]
+    pub layers: Vec<Layer>,
+
+    /// for type = "imagelayer"
+    pub image: String,
+    /// Whether the image drawn by this layer is repeated along the X axis
+    pub repeatx: bool,
+    /// Whether the image drawn by this layer is repeated along the Y axis
+    pub repeaty: bool,
 }
 
-#[derive(Clone, Debug, Default, DeJson)]
+#[derive(Clone, Debug, Default, DeJson, SerJson)]
 #[nserde(default)]
 pub struct Object {
     pub id: u32,
@@ -66,13 +79,22 @@ pub struct Object {
 
     #[nserde(rename = "type")]
     pub ty: String,
+    /// Replaces "type" since tiled 1.9
+    pub class: String,
     pub gid: Option<u32>,
     pub ellipse: Option<bool>,
+    pub point: Option<bool>,
     pub polygon: Option<Vec<PolyPoint>>,
+    pub polyline: Option<Vec<PolyPoint>>,
+    /// Only used for text objects
+    pub text: Option<Text>,
+    /// Reference to a template file, in case object is a template instance
+    pub template: Option<String>,
 
     pub properties: Vec<Property>,
     pub rotation: f32,
-    pub visible: bool,
+    /// None when left out, objects are visible unless their template says otherwise
+    pub visible: Option<bool>,
 
     pub height: f32,
     pub width: f32,
@@ -81,8 +103,32 @@ pub struct Object {
     pub y: f32,
 }
 
-#[derive(Copy, Clone, Debug, DeJson)]
+#[derive(Copy, Clone, Debug, DeJson, SerJson)]
 pub struct PolyPoint {
     pub x: f32,
     pub y: f32,
 }
+
+/// https://doc.mapeditor.org/en/stable/reference/json-map-format/#text
+#[derive(Clone, Debug, Default, DeJson, SerJson)]
+#[nserde(default)]
+pub struct Text {
+    pub bold: bool,
+    /// Hex-formatted color (#RRGGBB or #AARRGGBB) (default: #000000)
+    pub color: String,
+    /// Font family (default: sans-serif)
+    pub fontfamily: String,
+    /// Horizontal alignment (center, right, justify or left (default))
+    pub halign: String,
+    pub italic: bool,
+    pub kerning: Option<bool>,
+    /// Pixel size of font (default: 16)
+    pub pixelsize: u32,
+    pub strikeout: bool,
+    pub text: String,
+    pub underline: bool,
+    /// Vertical alignment (center, bottom or top (default))
+    pub valign: String,
+    /// Whether the text is wrapped within the object bounds
+    pub wrap: bool,
+}
@@ -0,0 +1,287 @@
+use macroquad::prelude::*;
+
+use crate::properties::{parse_color, Properties};
+use crate::tiled;
+
+use std::convert::TryFrom;
+
+/// Which parts of the tiles the colors of a wang set apply to
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum WangSetType {
+    Corner,
+    Edge,
+    Mixed,
+}
+
+/// A terrain of a wang set, like grass or water
+#[derive(Debug, Clone)]
+pub struct WangColor {
+    pub name: String,
+    /// Color used by tiled to display the terrain
+    pub color: Color,
+    /// Tile representing the terrain, if any
+    pub tile: Option<u32>,
+    pub probability: f32,
+    pub properties: Properties,
+}
+
+/// Set of tiles whose corners and/or edges are labelled with terrains, used to
+/// pick transition tiles automatically.
+/// https://doc.mapeditor.org/en/stable/manual/terrain/
+#[derive(Debug, Clone)]
+pub struct WangSet {
+    pub name: String,
+    pub ty: WangSetType,
+    /// Terrains of the set. Wang ids refer to them by index + 1, 0 meaning no terrain.
+    pub colors: Vec<WangColor>,
+    /// Tile ids with their wang id: the terrain of the top, top right, right,
+    /// bottom right, bottom, bottom left, left and top left parts of the tile
+    pub tiles: Vec<(u32, [u32; 8])>,
+    pub properties: Properties,
+}
+
+impl WangSet {
+    pub(crate) fn from_raw(wang_set: &tiled::WangSet) -> WangSet {
+        WangSet {
+            name: wang_set.name.clone(),
+            ty: match wang_set.ty.as_str() {
+                "edge" => WangSetType::Edge,
+                "mixed" => WangSetType::Mixed,
+                _ => WangSetType::Corner,
+            },
+            colors: wang_set
+                .colors
+                .iter()
+                .map(|color| WangColor {
+                    name: color.name.clone(),
+                    color: parse_color(&color.color).unwrap_or(WHITE),
+                    tile: u32::try_from(color.tile).ok(),
+                    probability: color.probability,
+                    properties: Properties::from_raw(&color.properties),
+                })
+                .collect(),
+            tiles: wang_set
+                .wangtiles
+                .iter()
+                .map(|tile| {
+                    let mut wang_id = [0; 8];
+                    for (slot, color) in wang_id.iter_mut().zip(&tile.wangid) {
+                        *slot = *color;
+                    }
+                    (tile.tileid, wang_id)
+                })
+                .collect(),
+            properties: Properties::from_raw(&wang_set.properties),
+        }
+    }
+
+    /// Terrains of tilesets made before tiled 1.5, as a corner wang set named "terrains".
+    /// None if the tileset has no terrains.
+    pub(crate) fn from_terrains(tileset: &tiled::Tileset) -> Option<WangSet> {
+        let terrains = tileset
+            .terrains
+            .as_ref()
+            .filter(|terrains| !terrains.is_empty())?;
+
+        Some(WangSet {
+            name: "terrains".to_owned(),
+            ty: WangSetType::Corner,
+            colors: terrains
+                .iter()
+                .map(|terrain| WangColor {
+                    name: terrain.name.clone(),
+                    color: WHITE,
+                    tile: u32::try_from(terrain.tile).ok(),
+                    probability: 1.,
+                    properties: Properties::default(),
+                })
+                .collect(),
+            tiles: tileset
+                .tiles
+                .iter()
+                .filter(|tile| tile.terrain.len() == 4)
+                .map(|tile| {
+                    // terrain corners are top left, top right, bottom left, bottom right
+                    let corner = |ix: usize| (tile.terrain[ix] + 1).max(0) as u32;
+                    let mut wang_id = [0; 8];
+                    wang_id[7] = corner(0);
+                    wang_id[1] = corner(1);
+                    wang_id[5] = corner(2);
+                    wang_id[3] = corner(3);
+                    (tile.id as u32, wang_id)
+                })
+                .collect(),
+            properties: Properties::default(),
+        })
+    }
+
+    /// Index used in wang ids for the color "name"
+    pub fn color_index(&self, name: &str) -> Option<u32> {
+        self.colors
+            .iter()
+            .position(|color| color.name == name)
+            .map(|ix| ix as u32 + 1)
+    }
+
+    pub fn wang_id(&self, tile: u32) -> Option<[u32; 8]> {
+        self.tiles
+            .iter()
+            .find(|(id, _)| *id == tile)
+            .map(|(_, wang_id)| *wang_id)
+    }
+
+    /// Whether the wang id slot "slot" is used by the tiles of this set
+    pub(crate) fn uses_slot(&self, slot: usize) -> bool {
+        match self.ty {
+            WangSetType::Corner => slot & 1 == 1,
+            WangSetType::Edge => slot & 1 == 0,
+            WangSetType::Mixed => true,
+        }
+    }
+
+    /// Tile matching "wang_id" the best, 0 slots match anything.
+    /// Mismatches on "required" slots are avoided first, then the others.
+    pub(crate) fn best_tile(&self, wang_id: &[u32; 8], required: &[bool; 8]) -> Option<u32> {
+        self.tiles
+            .iter()
+            .min_by_key(|(_, tile_wang_id)| {
+                (0..8)
+                    .filter(|slot| self.uses_slot(*slot) && wang_id[*slot] != 0)
+                    .filter(|slot| tile_wang_id[*slot] != wang_id[*slot])
+                    .map(|slot| if required[slot] { 100 } else { 1 })
+                    .sum::<u32>()
+            })
+            .map(|(id, _)| *id)
+    }
+}
+
+/// Wang id slots of a cell shared with its neighbour at "offset"
+pub(crate) fn shared_slots(offset: (i32, i32)) -> &'static [usize] {
+    match offset {
+        (-1, 0) => &[7, 6, 5],
+        (1, 0) => &[1, 2, 3],
+        (0, -1) => &[7, 0, 1],
+        (0, 1) => &[5, 4, 3],
+        (-1, -1) => &[7],
+        (1, -1) => &[1],
+        (-1, 1) => &[5],
+        (1, 1) => &[3],
+        _ => &[0, 1, 2, 3, 4, 5, 6, 7],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A set of the 16 tiles mixing two terrains, 1 and 2, on "slots".
+    /// Bit n of a tile id tells whether slots[n] is terrain 2.
+    fn wang_set(ty: WangSetType, slots: [usize; 4]) -> WangSet {
+        let color = |name: &str| WangColor {
+            name: name.to_owned(),
+            color: WHITE,
+            tile: None,
+            probability: 1.,
+            properties: Properties::default(),
+        };
+
+        WangSet {
+            name: "ground".to_owned(),
+            ty,
+            colors: vec![color("grass"), color("water")],
+            tiles: (0..16)
+                .map(|id| {
+                    let mut wang_id = [0; 8];
+                    for (bit, slot) in slots.iter().enumerate() {
+                        wang_id[*slot] = 1 + (id >> bit & 1);
+                    }
+                    (id, wang_id)
+                })
+                .collect(),
+            properties: Properties::default(),
+        }
+    }
+
+    fn corner_set() -> WangSet {
+        wang_set(WangSetType::Corner, [1, 3, 5, 7])
+    }
+
+    fn edge_set() -> WangSet {
+        wang_set(WangSetType::Edge, [0, 2, 4, 6])
+    }
+
+    #[test]
+    fn slots() {
+        let corner = corner_set();
+        let edge = edge_set();
+        for slot in 0..8 {
+            assert_eq!(corner.uses_slot(slot), slot % 2 == 1);
+            assert_eq!(edge.uses_slot(slot), slot % 2 == 0);
+        }
+
+        // the right side of a cell touches the left side of its right neighbour
+        assert_eq!(shared_slots((1, 0)), &[1, 2, 3]);
+        assert_eq!(shared_slots((-1, 0)), &[7, 6, 5]);
+        assert_eq!(shared_slots((0, 1)), &[5, 4, 3]);
+        assert_eq!(shared_slots((0, -1)), &[7, 0, 1]);
+        assert_eq!(shared_slots((1, 1)), &[3]);
+        assert_eq!(shared_slots((-1, -1)), &[7]);
+    }
+
+    #[test]
+    fn corners() {
+        let set = corner_set();
+        assert_eq!(set.color_index("water"), Some(2));
+        assert_eq!(set.color_index("lava"), None);
+
+        // water on the top right and bottom left corners
+        let wang_id = [0, 2, 0, 1, 0, 2, 0, 1];
+        assert_eq!(set.best_tile(&wang_id, &[false; 8]), Some(0b0101));
+        assert_eq!(set.wang_id(0b0101), Some(wang_id));
+
+        // edges are ignored by corner sets
+        let wang_id = [2, 2, 2, 2, 1, 1, 1, 1];
+        assert_eq!(set.best_tile(&wang_id, &[false; 8]), Some(0b0011));
+    }
+
+    #[test]
+    fn edges() {
+        let set = edge_set();
+
+        // water on the left edge, the rest unknown
+        let wang_id = [0, 0, 0, 0, 0, 0, 2, 0];
+        let tile = set.best_tile(&wang_id, &[false; 8]).unwrap();
+        assert_eq!(set.wang_id(tile).unwrap()[6], 2);
+
+        let wang_id = [1, 0, 2, 0, 2, 0, 1, 0];
+        assert_eq!(set.best_tile(&wang_id, &[false; 8]), Some(0b0110));
+    }
+
+    #[test]
+    fn required_slots_first() {
+        let mut set = corner_set();
+        // no tile with water on the top right corner only
+        set.tiles.retain(|(id, _)| *id != 0b0001);
+
+        let wang_id = [0, 2, 0, 1, 0, 1, 0, 1];
+        let mut required = [false; 8];
+        required[1] = true;
+        let tile = set.best_tile(&wang_id, &required).unwrap();
+        assert_eq!(set.wang_id(tile).unwrap()[1], 2);
+    }
+
+    #[test]
+    fn terrains() {
+        let tileset: tiled::Tileset = nanoserde::DeJson::deserialize_json(
+            r#"{"name":"ts","terrains":[{"name":"grass","tile":0},{"name":"water","tile":3}],
+            "tiles":[{"id":1,"terrain":[0,1,0,-1]}]}"#,
+        )
+        .unwrap();
+        let set = WangSet::from_terrains(&tileset).unwrap();
+
+        assert_eq!(set.ty, WangSetType::Corner);
+        assert_eq!(set.color_index("water"), Some(2));
+        assert_eq!(set.colors[1].tile, Some(3));
+        assert_eq!(set.wang_id(1), Some([0, 2, 0, 0, 0, 1, 0, 1]));
+    }
+}
@@ -0,0 +1,195 @@
+use macroquad::prelude::*;
+use nanoserde::DeJson;
+
+use crate::{tiled, Error, Map, Placement, Tile};
+
+/// A map placed in a world
+#[derive(Debug)]
+pub struct WorldMap {
+    /// Map file, as written in the world file
+    pub file_name: String,
+    /// Top left corner of the map in the world, in pixels
+    pub position: Vec2,
+    pub map: Map,
+}
+
+impl WorldMap {
+    /// Area covered by the map, in world pixels
+    pub fn rect(&self) -> Rect {
+        let size = self.map.pixel_size();
+        Rect::new(self.position.x, self.position.y, size.x, size.y)
+    }
+
+    /// Map pixel at world position "pos"
+    pub fn to_local(&self, pos: Vec2) -> Vec2 {
+        pos - self.position
+    }
+
+    /// World position of map pixel "pos"
+    pub fn to_world(&self, pos: Vec2) -> Vec2 {
+        pos + self.position
+    }
+
+    /// Map tile at world position "pos", None outside of the map
+    pub fn tile_at(&self, pos: Vec2) -> Option<(u32, u32)> {
+        let (x, y) = self.map.world_to_tile(self.to_local(pos));
+        let (width, height) = (self.map.raw_tiled_map.width, self.map.raw_tiled_map.height);
+
+        if x < 0 || y < 0 || x as u32 >= width || y as u32 >= height {
+            return None;
+        }
+        Some((x as u32, y as u32))
+    }
+}
+
+/// Maps of a tiled world, each at its own place
+/// https://doc.mapeditor.org/en/stable/manual/worlds/
+#[derive(Debug)]
+pub struct World {
+    /// In the world file order
+    pub maps: Vec<WorldMap>,
+    /// Hint from the world file that only the maps next to the current one should be shown
+    pub only_show_adjacent_maps: bool,
+
+    /// Deserialized json as is
+    pub raw_tiled_world: tiled::World,
+}
+
+impl World {
+    pub fn map(&self, file_name: &str) -> Option<&WorldMap> {
+        self.maps.iter().find(|map| map.file_name == file_name)
+    }
+
+    pub fn map_mut(&mut self, file_name: &str) -> Option<&mut WorldMap> {
+        self.maps.iter_mut().find(|map| map.file_name == file_name)
+    }
+
+    /// Map covering world position "pos".
+    /// When maps overlap, the first one in the world file wins.
+    pub fn map_at(&self, pos: Vec2) -> Option<&WorldMap> {
+        self.maps.iter().find(|map| map.rect().contains(pos))
+    }
+
+    /// Map and map tile covering world position "pos"
+    pub fn tile_at(&self, pos: Vec2) -> Option<(&WorldMap, u32, u32)> {
+        self.maps
+            .iter()
+            .filter(|map| map.rect().contains(pos))
+            .find_map(|map| map.tile_at(pos).map(|(x, y)| (map, x, y)))
+    }
+
+    /// Tile of layer "layer" at world position "pos".
+    /// None outside of the maps, on empty cells or if the map has no such layer.
+    pub fn get_tile(&self, layer: &str, pos: Vec2) -> Option<(&WorldMap, &Tile)> {
+        let (map, x, y) = self.tile_at(pos)?;
+        let layer = map.map.layers.get(layer)?;
+        let tile = layer.data.get((y * layer.width + x) as usize)?.as_ref()?;
+        Some((map, tile))
+    }
+
+    /// Area covered by all the maps, in world pixels
+    pub fn bounds(&self) -> Rect {
+        self.maps
+            .iter()
+            .map(|map| map.rect())
+            .reduce(|bounds, rect| bounds.combine_with(rect))
+            .unwrap_or(Rect::new(0., 0., 0., 0.))
+    }
+
+    /// Advance the tile animations of all the maps by "dt" seconds.
+    pub fn update(&mut self, dt: f32) {
+        for map in &mut self.maps {
+            map.map.update(dt);
+        }
+    }
+
+    /// Draw the part of the world inside "view", one world pixel per unit,
+    /// as seen through a camera covering "view".
+    pub fn draw(&self, view: Rect) {
+        for map in &self.maps {
+            if let Some(placement) = Self::visible_part(map, view) {
+                map.map.draw_at(placement);
+            }
+        }
+    }
+
+    /// Same as "draw", for one layer only. Maps without this layer are skipped.
+    pub fn draw_layer(&self, layer: &str, view: Rect) {
+        for map in self.maps.iter().filter(|map| map.map.contains_layer(layer)) {
+            if let Some(placement) = Self::visible_part(map, view) {
+                map.map
+                    .draw_layer_at(layer, placement)
+                    .unwrap_or_else(|err| panic!("{}", err));
+            }
+        }
+    }
+
+    /// Placement of the part of "map" inside "view".
+    /// Views are rarely aligned on tiles, so the map is placed in exact pixels
+    /// and only the drawn tile range gets rounded.
+    fn visible_part(map: &WorldMap, view: Rect) -> Option<Placement> {
+        let dest = map.rect().intersect(view)?;
+        Some(Placement {
+            dest,
+            view: map.to_local(dest.point()),
+            scale: vec2(1., 1.),
+        })
+    }
+}
+
+/// Place the maps of the tiled world file "data".
+/// "maps" are the already loaded maps, by the file name used in the world file.
+pub fn load_world(data: &str, maps: Vec<(&str, Map)>) -> Result<World, Error> {
+    let world: tiled::World = DeJson::deserialize_json(data)?;
+    if !world.patterns.is_empty() {
+        return Err(Error::UnsupportedWorldPatterns);
+    }
+
+    let mut maps = maps;
+    let mut world_maps = vec![];
+    for world_map in &world.maps {
+        let ix = maps
+            .iter()
+            .position(|(name, _)| *name == world_map.file_name)
+            .ok_or_else(|| Error::MapNotFound {
+                map: world_map.file_name.clone(),
+            })?;
+
+        world_maps.push(WorldMap {
+            file_name: world_map.file_name.clone(),
+            position: vec2(world_map.x as f32, world_map.y as f32),
+            map: maps.swap_remove(ix).1,
+        });
+    }
+
+    Ok(World {
+        maps: world_maps,
+        only_show_adjacent_maps: world.only_show_adjacent_maps,
+        raw_tiled_world: world,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn view_between_tiles() {
+        let data =
+            r#"{"width":20,"height":10,"tilewidth":32,"tileheight":32,"layers":[],"tilesets":[]}"#;
+        let map = crate::load_map(data, &[], &[]).unwrap();
+        let world = load_world(
+            r#"{"maps":[{"fileName":"a.json","x":100,"y":50}]}"#,
+            vec![("a.json", map)],
+        )
+        .unwrap();
+
+        let view = Rect::new(110., 60., 470., 200.);
+        let placement = World::visible_part(&world.maps[0], view).unwrap();
+
+        // drawn pixel for pixel, with the map at its place in the world
+        assert_eq!(placement.dest, view);
+        assert_eq!(placement.scale, vec2(1., 1.));
+        assert_eq!(placement.origin(), vec2(100., 50.));
+    }
+}
@@ -0,0 +1,230 @@
+use macroquad::prelude::*;
+use nanoserde::SerJson;
+
+use crate::properties::color_to_string;
+use crate::{tiled, Error, LayerType, Map, GID_MASK};
+
+impl Map {
+    /// Write the map back as tiled json, with the current layers, tiles,
+    /// objects and properties.
+    /// Fields the map does not keep at runtime, like layer ids, image layer
+    /// files or tile flip flags, are taken from "raw_tiled_map".
+    pub fn to_json(&self) -> Result<String, Error> {
+        Ok(self.to_raw()?.serialize_json())
+    }
+
+    fn to_raw(&self) -> Result<tiled::Map, Error> {
+        let mut map = self.raw_tiled_map.clone();
+
+        let mut next_layer_id = map.nextlayerid.max(max_layer_id(&map.layers) + 1);
+        map.layers = self.layers_to_raw(&self.root_layers, &mut next_layer_id)?;
+        map.nextlayerid = next_layer_id;
+        map.nextobjectid = map.nextobjectid.max(max_object_id(&map.layers) + 1);
+        map.properties = self.properties.to_raw();
+
+        // required by tiled, but not by load_map
+        if map.ty.is_empty() {
+            map.ty = "map".to_owned();
+        }
+        if map.orientation.is_empty() {
+            map.orientation = "orthogonal".to_owned();
+        }
+        if map.renderorder.is_empty() {
+            map.renderorder = "right-down".to_owned();
+        }
+
+        for raw_tileset in map.tilesets.iter_mut().filter(|t| t.source.is_none()) {
+            if let Some(tileset) = self.tilesets.get(&raw_tileset.name) {
+                raw_tileset.properties = tileset.properties.to_raw();
+            }
+        }
+        Ok(map)
+    }
+
+    fn layers_to_raw(
+        &self,
+        names: &[String],
+        next_layer_id: &mut u32,
+    ) -> Result<Vec<tiled::layer::Layer>, Error> {
+        let mut raw_layers = vec![];
+
+        for name in names {
+            let layer = self.layer(name)?;
+            let mut raw_layer = match find_raw_layer(&self.raw_tiled_map.layers, name) {
+                Some(raw_layer) => raw_layer.clone(),
+                None => {
+                    *next_layer_id += 1;
+                    tiled::layer::Layer {
+                        id: *next_layer_id - 1,
+                        name: name.clone(),
+                        ..Default::default()
+                    }
+                }
+            };
+
+            raw_layer.ty = match layer.ty {
+                LayerType::Tiles => "tilelayer",
+                LayerType::Objects => "objectgroup",
+                LayerType::Image => "imagelayer",
+                LayerType::Group => "group",
+            }
+            .to_owned();
+            raw_layer.width = layer.width;
+            raw_layer.height = layer.height;
+            raw_layer.properties = layer.properties.to_raw();
+            raw_layer.visible = layer.visible;
+            raw_layer.opacity = layer.opacity;
+            raw_layer.offsetx = Some(layer.offset.x as i32).filter(|x| *x != 0);
+            raw_layer.offsety = Some(layer.offset.y as i32).filter(|y| *y != 0);
+            raw_layer.parallaxx = Some(layer.parallax.x).filter(|x| *x != 1.);
+            raw_layer.parallaxy = Some(layer.parallax.y).filter(|y| *y != 1.);
+            raw_layer.tintcolor = Some(layer.tint)
+                .filter(|tint| *tint != WHITE)
+                .map(color_to_string);
+            raw_layer.chunks = None;
+
+            raw_layer.data = match layer.ty {
+                LayerType::Tiles => {
+                    let mut data = vec![];
+                    for (ix, tile) in layer.data.iter().enumerate() {
+                        let gid = match tile {
+                            Some(tile) => self.tileset(&tile.tileset)?.firstgid + tile.id,
+                            None => 0,
+                        };
+                        // keep the flip flags of tiles which did not change
+                        data.push(match raw_layer.data.get(ix) {
+                            Some(raw) if raw & GID_MASK == gid => *raw,
+                            _ => gid,
+                        });
+                    }
+                    data
+                }
+                _ => vec![],
+            };
+            raw_layer.objects = layer.objects.iter().map(|object| object.to_raw()).collect();
+            if let Some(image) = &layer.image {
+                raw_layer.repeatx = image.repeat_x;
+                raw_layer.repeaty = image.repeat_y;
+            }
+            raw_layer.layers = self.layers_to_raw(&layer.children, next_layer_id)?;
+
+            raw_layers.push(raw_layer);
+        }
+        Ok(raw_layers)
+    }
+}
+
+fn find_raw_layer<'a>(
+    layers: &'a [tiled::layer::Layer],
+    name: &str,
+) -> Option<&'a tiled::layer::Layer> {
+    layers.iter().find_map(|layer| {
+        if layer.name == name {
+            Some(layer)
+        } else {
+            find_raw_layer(&layer.layers, name)
+        }
+    })
+}
+
+fn max_layer_id(layers: &[tiled::layer::Layer]) -> u32 {
+    layers
+        .iter()
+        .map(|layer| layer.id.max(max_layer_id(&layer.layers)))
+        .max()
+        .unwrap_or(0)
+}
+
+fn max_object_id(layers: &[tiled::layer::Layer]) -> u32 {
+    layers
+        .iter()
+        .flat_map(|layer| {
+            let objects = layer.objects.iter().map(|object| object.id);
+            objects.chain(std::iter::once(max_object_id(&layer.layers)))
+        })
+        .max()
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::load_map;
+    use macroquad::prelude::*;
+
+    const MAP: &str = r##"{"width":2,"height":1,"tilewidth":8,"tileheight":8,"nextlayerid":4,
+        "properties":[
+            {"name":"cfg","type":"class","propertytype":"Cfg","value":{"hp":3,"name":"x"}},
+            {"name":"sky","type":"color","value":"#ff102030"}],
+        "layers":[
+            {"id":1,"name":"group","type":"group","tintcolor":"#ff0000","layers":[
+                {"id":2,"name":"ground","type":"tilelayer","width":2,"height":1,
+                 "data":[2147483649,2],"parallaxx":0.5,"opacity":0.5,"visible":false}]},
+            {"id":3,"name":"spawns","type":"objectgroup","objects":[
+                {"id":7,"name":"spawn","class":"Spawn","x":1,"y":2,"width":3,"height":4,"visible":false,
+                 "polygon":[{"x":0,"y":0},{"x":1,"y":1}],
+                 "properties":[{"name":"weight","type":"float","value":1.5}]}]}],
+        "tilesets":[{"firstgid":1,"name":"ts","image":"ts.png","columns":2,"tilecount":2,
+            "tilewidth":8,"tileheight":8,"properties":[{"name":"solid","type":"bool","value":true}]}]}"##;
+
+    #[test]
+    fn round_trip() {
+        let textures = [("ts.png", Texture2D::empty())];
+        let mut map = load_map(MAP, &textures, &[]).unwrap();
+        map.set_tile("ground", 1, 0, None).unwrap();
+
+        let written = load_map(&map.to_json().unwrap(), &textures, &[]).unwrap();
+
+        assert_eq!(written.root_layers, map.root_layers);
+        assert_eq!(written.properties, map.properties);
+        assert_eq!(
+            written.tilesets["ts"].properties,
+            map.tilesets["ts"].properties
+        );
+
+        let mut names = map.layers().keys().collect::<Vec<_>>();
+        names.sort();
+        let mut written_names = written.layers().keys().collect::<Vec<_>>();
+        written_names.sort();
+        assert_eq!(written_names, names);
+
+        for (name, layer) in map.layers() {
+            let written = &written.layers()[name];
+            assert_eq!(written.ty, layer.ty, "{}", name);
+            assert_eq!(written.visible, layer.visible, "{}", name);
+            assert_eq!(written.opacity, layer.opacity, "{}", name);
+            assert_eq!(written.parallax, layer.parallax, "{}", name);
+            assert_eq!(written.tint, layer.tint, "{}", name);
+            assert_eq!(written.parent, layer.parent, "{}", name);
+            assert_eq!(written.children, layer.children, "{}", name);
+            assert_eq!(
+                written
+                    .data
+                    .iter()
+                    .map(|tile| tile.as_ref().map(|tile| tile.id))
+                    .collect::<Vec<_>>(),
+                layer
+                    .data
+                    .iter()
+                    .map(|tile| tile.as_ref().map(|tile| tile.id))
+                    .collect::<Vec<_>>(),
+                "{}",
+                name
+            );
+            assert_eq!(written.objects.len(), layer.objects.len(), "{}", name);
+            for (written, object) in written.objects.iter().zip(&layer.objects) {
+                assert_eq!(written.id, object.id);
+                assert_eq!(written.name, object.name);
+                assert_eq!(written.ty, object.ty);
+                assert_eq!(written.shape, object.shape);
+                assert_eq!(written.visible, object.visible);
+                assert_eq!(written.properties, object.properties);
+            }
+        }
+
+        // the flip flags of the first tile are kept from the file
+        assert_eq!(
+            written.raw_tiled_map.layers[0].layers[0].data,
+            [2147483649, 0]
+        );
+    }
+}